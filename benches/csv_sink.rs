@@ -0,0 +1,83 @@
+//! Time per row of logging polls through a `CsvSink`, against reopening and chmodding the file for every row
+//! as raplrs did before the sink - run with `cargo bench --bench csv_sink`
+
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use raplrs::common;
+use raplrs::fixture::FakePowercap;
+use raplrs::logger::{self, CsvSink};
+use raplrs::models::{self, FixtureZone};
+use raplrs::sources::PowercapSource;
+
+use std::fs::{self, OpenOptions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::{Instant, SystemTime};
+
+const ROWS: u32 = 20000;
+
+/// Rows of a two package machine with one subzone each
+fn zones(sysfs_root: &Path) -> Vec<models::RAPLData> {
+    let packages: Vec<FixtureZone> = (0..2).map(|i| FixtureZone{
+        name: format!("package-{}", i),
+        control_type: None,
+        max_energy_range_uj: 262143328850,
+        energy_uj: vec![1000000],
+        subzones: vec![FixtureZone{
+            name: "core".to_string(),
+            control_type: None,
+            max_energy_range_uj: 262143328850,
+            energy_uj: vec![500000],
+            subzones: vec![]
+        }]
+    }).collect();
+    FakePowercap::create(sysfs_root, &packages).unwrap();
+
+    return common::setup_rapl_data(&PowercapSource::new(sysfs_root)).unwrap();
+}
+
+/// The logging of every poll before `CsvSink`: open, append one row, chmod
+fn reopen_per_row(file_name: &str, zone: &models::RAPLData) {
+    let file = OpenOptions::new().create(true).append(true).open(file_name).unwrap();
+    let mut wtr = csv::WriterBuilder::default().has_headers(false).from_writer(file);
+    wtr.serialize(zone).unwrap();
+    wtr.flush().unwrap();
+    fs::set_permissions(file_name, fs::Permissions::from_mode(0o666)).unwrap();
+}
+
+fn report(name: &str, start_time: Instant) {
+    let per_row = start_time.elapsed().as_secs_f64() / ROWS as f64;
+    println!("{:<16}{:>10.2} µs/row", name, per_row * 1e6);
+}
+
+fn main() {
+    let root = tempfile::tempdir().unwrap();
+    let zones = zones(&root.path().join("sysfs"));
+    logger::start_session(root.path(), models::SessionManifest{
+        tool: "bench".to_string(),
+        name: "".to_string(),
+        command: vec![],
+        source: Some("powercap".to_string()),
+        isolate_file: None,
+        run_as: None,
+        start_time: 0.,
+        end_time: None,
+        host: common::host_info(),
+        files: vec![]
+    }, None).unwrap();
+
+    let file_name = root.path().join("reopen.csv").display().to_string();
+    let start_time = Instant::now();
+    for (_, zone) in (0..ROWS).zip(zones.iter().cycle()) {
+        reopen_per_row(&file_name, zone);
+    }
+    report("reopen per row", start_time);
+
+    let start_time = Instant::now();
+    let mut sink = CsvSink::create(SystemTime::now(), "bench".to_string(), "".to_string()).unwrap();
+    for (_, zone) in (0..ROWS).zip(zones.iter().cycle()) {
+        sink.write(zone).unwrap();
+    }
+    sink.flush().unwrap();
+    report("csv sink", start_time);
+}
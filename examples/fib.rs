@@ -0,0 +1,29 @@
+//! In-process counterpart of `benchmark/micro/fib.sh`
+//!
+//! $ sudo cargo run --release --example fib
+
+#![allow(clippy::needless_return)]
+
+use raplrs::harness::Harness;
+use raplrs::Meter;
+
+fn fib(n: u64) -> u64 {
+    let (mut a, mut b) = (0u64, 1u64);
+    for _ in 0..n {
+        let next = a.wrapping_add(b);
+        a = b;
+        b = next;
+    }
+
+    return a;
+}
+
+fn main() -> Result<(), raplrs::Error> {
+    let meter = Meter::detect()?;
+    let report = Harness::new(&meter).bench("fib", || fib(std::hint::black_box(500000)))?;
+
+    report.print();
+    println!("Report written to {}", report.save()?);
+
+    return Ok(());
+}
@@ -1,54 +1,52 @@
 use std::collections::HashMap;
+use crate::error::{Error, Result};
 use crate::models;
 use crate::logger;
+use crate::stats;
+use crate::thermal;
+use crate::sources::EnergySource;
 
 use std::fs;
-use std::fs::DirEntry;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
-use std::process::{Command, Stdio};
 
 use ncurses;
 use regex::Regex;
 
-pub(crate) const UJ_TO_J_FACTOR: f64 = 1000000.;
+pub const UJ_TO_J_FACTOR: f64 = 1000000.;
 
 // ncurses
 const COLOUR_BLACK: i16 = 0;
 const DEFAULT_COLOUR: i16 = -1;
-pub(crate) const HEADER_PAIR: i16 = 1;
-pub(crate) const KEY_CODE_EXIT: i32 = 113;  // q
+pub const HEADER_PAIR: i16 = 1;
+pub const KEY_CODE_EXIT: i32 = 113;  // q
 
 // threads
-pub(crate) const THREAD_KILL: i8 = 1;
+pub const THREAD_KILL: i8 = 1;
 
-pub(crate) fn read_power(file_path: String) -> f64 {
-    let power = fs::read(format!("{}/energy_uj", file_path.to_owned())).expect(format!("Couldn't read file {}/energy_uj", file_path.to_owned()).as_str());
+// benchmarks
+pub const MIN_ADAPTIVE_RUNS: u64 = 3;
 
-    return reading_as_float(&power) / UJ_TO_J_FACTOR;
-}
-
-pub(crate) fn read_power_limit(file_path: String) -> f64 {
-    let limit = fs::read(format!("{}/max_energy_range_uj", file_path.to_owned())).expect(format!("Couldn't read file {}/max_energy_range_uj", file_path.to_owned()).as_str());
+// logs
+/// Version of the .csv columns, written as a `#` comment above the header
+pub const CSV_SCHEMA_VERSION: u32 = 3;
 
-    return reading_as_float(&limit) / UJ_TO_J_FACTOR;
-}
-
-pub(crate) fn reading_as_float(reading: &Vec<u8>) -> f64 {
+pub fn reading_as_float(reading: &[u8]) -> std::result::Result<f64, std::num::ParseFloatError> {
     let power = String::from_utf8_lossy(reading);
-    let power_as_float = power.replace("\n", "").parse::<f64>().unwrap();
-    return power_as_float;
+    return power.replace("\n", "").parse::<f64>();
 }
 
-pub(crate) fn spacing(line: String) -> String {
+pub fn spacing(line: String) -> String {
     // 25 and 30 makes for fucky formatting:
     // w/h is misaligned; line break on small monitors, respectively
     let col_spacing = 28;
     return " ".repeat(col_spacing - line.len());
 }
 
-pub(crate) fn print_headers(ncurses: bool) {
-    let headers = vec!["zone", "time(s)", "J", "avg watt", "avg watt curr", "w/h", "kw/h", "temp(c)"];
+pub fn print_headers(ncurses: bool) {
+    let headers = vec!["zone", "time(s)", "J", "avg watt", "avg watt curr", "w/h", "kw/h", "pkg temp(c)", "core max(c)", "core avg(c)"];
     let mut line: String = "".to_owned();
 
     for h in headers {
@@ -56,7 +54,7 @@ pub(crate) fn print_headers(ncurses: bool) {
     }
 
     line = line.trim().to_string();
-    line.push_str("\n");
+    line.push('\n');
     if ncurses {
         ncurses::attron(ncurses::A_BOLD());
         ncurses::addstr(line.as_str());
@@ -71,11 +69,11 @@ pub(crate) fn print_headers(ncurses: bool) {
 macro_rules! print_headers {
     ($ncurses: expr) => {
         // what the fuck
-        {crate::common::print_headers($ncurses);}
+        {$crate::common::print_headers($ncurses);}
     };
     () => {
         // what the fuck
-        {crate::common::print_headers(false);}
+        {$crate::common::print_headers(false);}
     }
 }
 
@@ -87,24 +85,32 @@ macro_rules! ncprint {
     };
 }
 
-pub(crate) fn print_result_line(zones: &Vec<models::RAPLData>, ncurses: bool) {
+pub fn print_result_line(zones: &Vec<models::RAPLData>, ncurses: bool) {
     let mut line: String = "\r".repeat(zones.len()).to_owned();
 
     for zone in zones {
         let watt_hours = watt_hours(zone.power_j);
         let kwatt_hours = kwatt_hours(zone.power_j);
-        let fields = vec![zone.time_elapsed, zone.power_j, zone.watts, zone.watts_since_last, watt_hours, kwatt_hours, zone.temp];
+        let fields = vec![zone.time_elapsed, zone.power_j, zone.watts, zone.watts_since_last, watt_hours, kwatt_hours];
+        let temps = vec![zone.package_temp, zone.core_temp_max, zone.core_temp_avg];
         let zone_name = zone.zone.to_owned();
         line.push_str(format!("{}{}", zone_name.to_owned(), spacing(zone_name.to_owned())).as_str());
 
         for f in fields {
             line.push_str(format!("{:.5}{}", f, spacing(format!("{:.5}", f))).as_str());
         }
+        for t in temps {
+            let t = match t {
+                Some(t) => format!("{:.5}", t),
+                None => "-".to_string()
+            };
+            line.push_str(format!("{}{}", t, spacing(t.to_owned())).as_str());
+        }
 
         line = line.trim().to_string();
 
         if zone.zone != zones.last().unwrap().zone {
-            line.push_str("\n");
+            line.push('\n');
         }
     }
 
@@ -121,77 +127,275 @@ pub(crate) fn print_result_line(zones: &Vec<models::RAPLData>, ncurses: bool) {
 macro_rules! print_result_line {
     ($zones: expr, $ncurses: expr) => {
         // what the fuck
-        {crate::common::print_result_line($zones, $ncurses);}
+        {$crate::common::print_result_line($zones, $ncurses);}
     };
     ($zones: expr) => {
         // what the fuck
-        {crate::common::print_result_line($zones, false);}
+        {$crate::common::print_result_line($zones, false);}
     }
 }
 
-pub(crate) fn watt_hours(power_j: f64) -> f64 {
-    return power_j / 3600.;
+/// Summarise the last measurement of each benchmark iteration, per zone
+pub fn summarize_runs(runs: &[Vec<models::RAPLData>]) -> Vec<models::ZoneSummary> {
+    let mut out = vec![];
+    let zones = match runs.first() {
+        Some(r) => r.iter().map(|z| z.zone.to_owned()).collect::<Vec<String>>(),
+        None => return out
+    };
+
+    for zone in zones {
+        let data: Vec<&models::RAPLData> = runs.iter().flatten().filter(|z| z.zone == zone).collect();
+        out.push(models::ZoneSummary{
+            zone,
+            power_j: stats::Summary::of(&data.iter().map(|z| z.power_j).collect::<Vec<f64>>()),
+            time_elapsed: stats::Summary::of(&data.iter().map(|z| z.time_elapsed).collect::<Vec<f64>>()),
+            watts: stats::Summary::of(&data.iter().map(|z| z.watts).collect::<Vec<f64>>())
+        });
+    }
+
+    return out;
 }
 
-pub(crate) fn kwatt_hours(power_j: f64) -> f64 {
-    return watt_hours(power_j) / 1000.;
+/// Coefficient of variation of the package energy across benchmark iterations.
+/// The energy of all packages is summed per iteration - if there are none, the first zone is used instead.
+pub fn package_energy_cv(runs: &[Vec<models::RAPLData>]) -> f64 {
+    let energy: Vec<f64> = runs.iter().map(|zones| {
+        let packages: Vec<&models::RAPLData> = zones.iter().filter(|z| z.zone.starts_with("package-")).collect();
+        if packages.is_empty() {
+            return zones.first().map(|z| z.power_j).unwrap_or(0.);
+        }
+        packages.iter().map(|z| z.power_j).sum()
+    }).collect();
+
+    return stats::Summary::of(&energy).cv();
 }
 
-pub(crate) fn list_rapl() -> Vec<models::RAPLZone> {
-    let base_path = "/sys/devices/virtual/powercap/intel-rapl/";
-    let cpus = fs::read_dir(base_path).unwrap();
-    let mut zones: Vec<models::RAPLZone> = vec![];
+/// Whether to stop after `runs` benchmark iterations, and why
+pub fn should_stop_benchmarking(options: &models::BenchmarkOptions, runs: u64, cv: Option<f64>,
+                                start_time: Instant) -> Option<models::StopReason> {
+    match options.max_time {
+        Some(max_time) if start_time.elapsed().as_secs() >= max_time => return Some(models::StopReason::MaxTime),
+        _ => {}
+    }
 
-    for cpu in cpus {
-        let pkg = parse_rapl_dir(cpu.unwrap());
-        match pkg {
-            Some(x) => zones.push(x),
-            None => continue
+    return match options.target_cv {
+        Some(target_cv) => {
+            // the first runs' cv is too unreliable to stop on
+            let min_runs = options.n.max(MIN_ADAPTIVE_RUNS);
+            if runs >= min_runs && cv.map(|cv| cv <= target_cv).unwrap_or(false) {
+                Some(models::StopReason::Converged)
+            } else if runs >= options.max_runs {
+                Some(models::StopReason::MaxRuns)
+            } else {
+                None
+            }
+        },
+        None if runs >= options.n => Some(models::StopReason::Iterations),
+        None => None
+    };
+}
+
+pub fn describe_stop_reason(reason: models::StopReason, cv: Option<f64>, options: &models::BenchmarkOptions) -> String {
+    let cv = match cv {
+        Some(cv) => format!("package energy cv {:.5}", cv),
+        None => "package energy cv not tracked".to_string()
+    };
+
+    return match reason {
+        models::StopReason::Iterations => format!("all {} iterations done, {}", options.n, cv),
+        models::StopReason::Converged => format!("{} <= target {}", cv, options.target_cv.unwrap_or(0.)),
+        models::StopReason::MaxRuns => format!("reached max runs {}, {}", options.max_runs, cv),
+        models::StopReason::MaxTime => format!("reached max time {}s, {}", options.max_time.unwrap_or(0), cv)
+    };
+}
+
+pub fn print_summary(zones: &Vec<models::ZoneSummary>) {
+    let confidence = format!("{}% ci", stats::CONFIDENCE_LEVEL * 100.);
+    println!("{:<20}{:<12}{:<14}{:<14}{:<14}{:<14}{:<14}{}", "zone", "metric", "mean", "median", "std dev", "min", "max", confidence);
+
+    for zone in zones {
+        let metrics = vec![("J", &zone.power_j), ("time(s)", &zone.time_elapsed), ("avg watt", &zone.watts)];
+        for (i, (metric, s)) in metrics.into_iter().enumerate() {
+            let zone_name = if i == 0 { zone.zone.as_str() } else { "" };
+            println!("{:<20}{:<12}{:<14.5}{:<14.5}{:<14.5}{:<14.5}{:<14.5}[{:.5}, {:.5}]",
+                     zone_name, metric, s.mean, s.median, s.std_dev, s.min, s.max, s.ci_low, s.ci_high);
         }
+    }
+}
 
-        let path = &zones.last().unwrap().path;
-        let pkg = fs::read_dir(path).unwrap();
+/// Compare the energy of every zone of each program against the first program
+pub fn compare_runs(names: &[String], runs: &[Vec<Vec<models::RAPLData>>]) -> Vec<models::ZoneComparison> {
+    let mut out = vec![];
+    let energy_of = |program: usize, zone: &str| -> Vec<f64> {
+        return runs[program].iter().flatten().filter(|z| z.zone == zone).map(|z| z.power_j).collect();
+    };
+    let zones = match runs.first().and_then(|r| r.first()) {
+        Some(r) => r.iter().map(|z| z.zone.to_owned()).collect::<Vec<String>>(),
+        None => return out
+    };
 
-        for core in pkg {
-            let core_zone = parse_rapl_dir(core.unwrap());
-            match core_zone {
-                Some(x) => zones.push(x),
-                None => continue
-            }
+    for zone in &zones {
+        let baseline = energy_of(0, zone);
+        for program in 1..runs.len() {
+            let other = energy_of(program, zone);
+            let (baseline_mean, mean) = (stats::mean(&baseline), stats::mean(&other));
+            // e.g. an idle dram zone, or no runs at all
+            let diff_percent = match baseline_mean != 0. && baseline_mean.is_finite() {
+                true => Some((mean - baseline_mean) / baseline_mean * 100.),
+                false => None
+            };
+
+            out.push(models::ZoneComparison{
+                zone: zone.to_owned(),
+                baseline: names[0].to_owned(),
+                program: names[program].to_owned(),
+                baseline_power_j: baseline_mean,
+                power_j: mean,
+                diff_power_j: mean - baseline_mean,
+                diff_percent,
+                welch_p: stats::welch_t_test(&other, &baseline).2,
+                mann_whitney_p: stats::mann_whitney_u(&other, &baseline).1,
+                cohens_d: stats::cohens_d(&other, &baseline),
+                cliffs_delta: stats::cliffs_delta(&other, &baseline)
+            });
         }
     }
 
-    return zones;
+    return out;
 }
 
-fn parse_rapl_dir(item: DirEntry) -> Option<models::RAPLZone> {
-    let cleaned_item_name = item.path().display().to_string().split("/").last().unwrap().to_owned();
+pub fn print_comparisons(comparisons: &Vec<models::ZoneComparison>) {
+    println!("{:<20}{:<24}{:<14}{:<14}{:<12}{:<12}{:<12}{:<12}cliff's delta",
+             "zone", "program", "J", "diff J", "diff %", "welch p", "mwu p", "cohen's d");
 
-    if !cleaned_item_name.contains("intel-rapl") {
-        return None;
+    for c in comparisons {
+        let diff_percent = c.diff_percent.map(|d| format!("{:.2}", d)).unwrap_or_else(|| "-".to_string());
+        println!("{:<20}{:<24}{:<14.5}{:<14.5}{:<12}{:<12.5}{:<12.5}{:<12.3}{:.3}",
+                 c.zone, c.program, c.power_j, c.diff_power_j, diff_percent,
+                 c.welch_p, c.mann_whitney_p, c.cohens_d, c.cliffs_delta);
     }
+}
 
-    let item_path = item.path().display().to_string();
-    let item_name_data = fs::read(format!("{}/name", item_path))
-        .expect(format!("Couldn't read file {}/name", item_path).as_str());
-    let item_name = String::from_utf8_lossy(&item_name_data);
+/// Parse a sweep axis - either a range such as `n=10..40:10`, inclusive with an optional step,
+/// or a list such as `threads=1,2,4`
+pub fn parse_sweep_axis(input: &str) -> Result<models::SweepAxis> {
+    let (name, values) = input.split_once('=')
+        .ok_or_else(|| Error::Usage(format!("Invalid parameter {}, expected e.g. n=10..40:10 or n=1,2,4", input)))?;
+    let range = Regex::new(r"^(-?\d+)\.\.(-?\d+)(?::(\d+))?$").unwrap();
+
+    let values = match range.captures(values) {
+        Some(c) => {
+            let out_of_range = |_| Error::Usage(format!("Range of parameter {} is out of bounds", name));
+            let start: i64 = c[1].parse().map_err(out_of_range)?;
+            let end: i64 = c[2].parse().map_err(out_of_range)?;
+            let step: i64 = match c.get(3) {
+                Some(step) => step.as_str().parse().map_err(out_of_range)?,
+                None => 1
+            };
+            if step == 0 || start > end {
+                return Err(Error::Usage(format!(
+                    "Invalid range for parameter {}, the start must not exceed the end and the step must be positive", name)));
+            }
+            (start..=end).step_by(step as usize).map(|v| v.to_string()).collect()
+        },
+        None => values.split(',').map(|v| v.trim().to_string()).collect()
+    };
 
-    return Some(models::RAPLZone{
-        path: item.path().display().to_string(),
-        name: item_name.to_string().replace("\n", "")
-    });
+    return Ok(models::SweepAxis{ name: name.trim().to_string(), values });
+}
+
+/// Every combination of the axes' values, varying the last axis fastest
+pub fn sweep_combinations(axes: &Vec<models::SweepAxis>) -> Vec<Vec<(String, String)>> {
+    let mut out: Vec<Vec<(String, String)>> = vec![vec![]];
+
+    for axis in axes {
+        out = out.iter().flat_map(|params| {
+            axis.values.iter().map(move |v| {
+                let mut params = params.to_owned();
+                params.push((axis.name.to_owned(), v.to_owned()));
+                params
+            })
+        }).collect();
+    }
+
+    return out;
+}
+
+/// Replace `{name}` with the value of each parameter
+pub fn substitute_params(input: &str, params: &Vec<(String, String)>) -> String {
+    let mut out = input.to_string();
+    for (name, value) in params {
+        out = out.replace(format!("{{{}}}", name).as_str(), value);
+    }
+
+    return out;
+}
+
+pub fn print_sweep_results(axes: &Vec<models::SweepAxis>, points: &Vec<models::SweepPoint>) {
+    let mut line = String::new();
+    for axis in axes {
+        line.push_str(format!("{:<12}", axis.name).as_str());
+    }
+    println!("{}{:<20}{:<14}{:<14}{:<14}avg watt", line, "zone", "J", "std dev", "time(s)");
+
+    for point in points {
+        let mut params = String::new();
+        for (_, value) in &point.params {
+            params.push_str(format!("{:<12}", value).as_str());
+        }
+
+        for zone in &point.summary.zones {
+            println!("{}{:<20}{:<14.5}{:<14.5}{:<14.5}{:.5}", params, zone.zone, zone.power_j.mean,
+                     zone.power_j.std_dev, zone.time_elapsed.mean, zone.watts.mean);
+        }
+    }
+}
+
+pub fn print_scaling_analysis(analysis: &models::ScalingAnalysis, predict: &Vec<f64>) {
+    let params: Vec<String> = analysis.params.iter().map(|(n, v)| format!("{}={}", n, v)).collect();
+    println!("{} {}({} points)", analysis.zone, params.iter().map(|p| p.to_owned() + " ").collect::<String>(), analysis.points);
+
+    let mut line = format!("{:<14}{:<12}", "model", "r^2");
+    for x in predict {
+        line.push_str(format!("{:<16}", format!("J at {}={}", analysis.x, x)).as_str());
+    }
+    println!("{}J =", line);
+
+    for f in &analysis.fits {
+        let model = serde_json::to_string(&f.fit.model).unwrap().replace("\"", "");
+        let mut line = format!("{:<14}{:<12.5}", model, f.fit.r_squared);
+        for (_, y) in &f.predictions {
+            line.push_str(format!("{:<16.5}", y).as_str());
+        }
+        println!("{}{}", line, f.fit.describe());
+    }
+}
+
+pub fn watt_hours(power_j: f64) -> f64 {
+    return power_j / 3600.;
 }
 
-pub(crate) fn setup_rapl_data() -> Vec<models::RAPLData> {
-    let sys_zones = list_rapl();
+pub fn kwatt_hours(power_j: f64) -> f64 {
+    return watt_hours(power_j) / 1000.;
+}
+
+/// Initial readings of every zone, failing if the source has none
+pub fn setup_rapl_data(source: &dyn EnergySource) -> Result<Vec<models::RAPLData>> {
+    let sys_zones = source.zones()?;
+    if sys_zones.is_empty() {
+        return Err(Error::NoZones);
+    }
     let mut zones: Vec<models::RAPLData> = vec![];
-    let temperature = get_cpu_temp();
+    let temperatures = read_temperatures();
 
     for z in sys_zones {
-        let start_power = read_power(z.path.to_owned());
-        let data = models::RAPLData{
+        let start_power = source.read_energy(&z.path)?;
+        let mut data = models::RAPLData{
             path: z.path,
-            zone: z.name,
+            run_id: "".to_string(),
+            sample: 0,
+            timestamp: 0.,
+            zone: z.id,
             time_elapsed: 0.,
             power_j: 0.,
             watts: 0.,
@@ -199,15 +403,21 @@ pub(crate) fn setup_rapl_data() -> Vec<models::RAPLData> {
             start_power,
             prev_power: 0.,
             prev_power_reading: start_power,
-            temp: temperature
+            package_temp: None,
+            core_temp_max: None,
+            core_temp_avg: None
         };
+        temperatures.apply(&mut data);
         zones.push(data);
     }
 
-    return zones;
+    return Ok(zones);
 }
 
-pub(crate) fn setup_ncurses() {
+static NCURSES_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn setup_ncurses() {
+    NCURSES_ACTIVE.store(true, Ordering::SeqCst);
     let w = ncurses::initscr();
     ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_INVISIBLE);
     ncurses::nodelay(w, true);
@@ -218,21 +428,29 @@ pub(crate) fn setup_ncurses() {
     }
 }
 
-pub(crate) fn kill_ncurses() {
+pub fn kill_ncurses() {
+    NCURSES_ACTIVE.store(false, Ordering::SeqCst);
     ncurses::endwin();
     ncurses::reset_shell_mode();
 }
 
-pub(crate) fn calculate_power_metrics(zone: models::RAPLData, now: Instant,
-                                      start_time: Instant, prev_time: Instant) -> models::RAPLData {
-    let cur_power_j = read_power(zone.path.to_owned());
+/// Leave ncurses mode if it is active, e.g. before printing an error
+pub fn restore_terminal() {
+    if NCURSES_ACTIVE.load(Ordering::SeqCst) {
+        kill_ncurses();
+    }
+}
+
+pub fn calculate_power_metrics(source: &dyn EnergySource, zone: models::RAPLData, now: Instant,
+                               start_time: Instant, prev_time: Instant) -> Result<models::RAPLData> {
+    let cur_power_j = source.read_energy(&zone.path)?;
 
     #[allow(unused_assignments)]
     let mut power_j = 0.;
     let mut watts = 0.;
     let mut watts_since_last = 0.;
 
-    let power_limit = read_power_limit(zone.path.to_owned());
+    let power_limit = source.read_energy_range(&zone.path)?;
 
     // if RAPL overflow has occurred
     // or if we have done a full RAPL cycle
@@ -257,10 +475,11 @@ pub(crate) fn calculate_power_metrics(zone: models::RAPLData, now: Instant,
         watts_since_last = (power_j - zone.power_j) / now.duration_since(prev_time).as_secs_f64();
     }
 
-    let temperature = get_cpu_temp();
-
-    return models::RAPLData{
+    return Ok(models::RAPLData{
         path: zone.path,
+        run_id: zone.run_id,
+        sample: zone.sample,
+        timestamp: zone.timestamp,
         zone: zone.zone,
         time_elapsed: start_time.elapsed().as_secs_f64(),
         power_j,
@@ -269,17 +488,20 @@ pub(crate) fn calculate_power_metrics(zone: models::RAPLData, now: Instant,
         start_power: zone.start_power,
         prev_power: zone.power_j,
         prev_power_reading: cur_power_j,
-        temp: temperature
-    }
+        package_temp: zone.package_temp,
+        core_temp_max: zone.core_temp_max,
+        core_temp_avg: zone.core_temp_avg
+    });
 }
 
-pub(crate) fn calculate_isolated_power_metrics(
+pub fn calculate_isolated_power_metrics(
+    source: &dyn EnergySource,
     zone: models::RAPLData,
     now: Instant,
     start_time: Instant,
     prev_time: Instant,
-    isolated_zone: &models::IsolateData) -> models::RAPLData {
-    let mut data = calculate_power_metrics(zone, now, start_time, prev_time);
+    isolated_zone: &models::IsolateData) -> Result<models::RAPLData> {
+    let mut data = calculate_power_metrics(source, zone, now, start_time, prev_time)?;
 
     data = models::RAPLData{
         power_j: data.power_j - isolated_zone.power_j.avg,
@@ -288,109 +510,244 @@ pub(crate) fn calculate_isolated_power_metrics(
         ..data
     };
 
-    return data
+    return Ok(data);
 }
 
-pub(crate) fn update_measurements(zones: Vec<models::RAPLData>, now: Instant, start_time: Instant,
-                                  prev_time: Instant, system_start_time: SystemTime, tool_name: String,
-                                  benchmark_name: String, isolate_map: Option<HashMap<String, models::IsolateData>>) -> Vec<models::RAPLData> {
+pub fn update_measurements(source: &dyn EnergySource, zones: Vec<models::RAPLData>, now: Instant, start_time: Instant,
+                           prev_time: Instant, sink: &mut logger::CsvSink,
+                           isolate_map: Option<HashMap<String, models::IsolateData>>) -> Result<Vec<models::RAPLData>> {
     let mut res: Vec<models::RAPLData> = vec![];
+    let timestamp = logger::unix_time(SystemTime::now());
+    let temperatures = read_temperatures();
 
     for zone in zones {
-        let new_zone: models::RAPLData = match isolate_map.to_owned() {
+        let mut new_zone: models::RAPLData = match &isolate_map {
             Some(map) => {
-                let iz = map.get(zone.zone.as_str()).unwrap();
-                calculate_isolated_power_metrics(zone.to_owned(), now, start_time, prev_time, iz)
+                // read_isolated_data checks that there is data for every zone, but maps may come from elsewhere
+                let iz = map.get(zone.zone.as_str()).ok_or_else(|| Error::malformed(
+                    "the isolation data", format!("there is no data for zone {}", zone.zone)))?;
+                calculate_isolated_power_metrics(source, zone.to_owned(), now, start_time, prev_time, iz)?
             },
             _ => {
-                calculate_power_metrics(zone.to_owned(), now, start_time, prev_time)
+                calculate_power_metrics(source, zone.to_owned(), now, start_time, prev_time)?
             }
         };
-        logger::log_poll_result(system_start_time, tool_name.to_owned(), new_zone.to_owned(), benchmark_name.to_owned());
+        new_zone.run_id = sink.run_id.to_owned();
+        new_zone.sample = zone.sample + 1;
+        new_zone.timestamp = timestamp;
+        temperatures.apply(&mut new_zone);
+        sink.write(&new_zone)?;
         res.push(new_zone);
     }
 
-    return res.to_vec();
+    return Ok(res);
 }
 
-pub(crate) fn should_terminate(limit: u64, now: Instant, start_time: Instant) -> bool {
+pub fn should_terminate(limit: u64, now: Instant, start_time: Instant) -> bool {
     return limit > 0 && now.duration_since(start_time).as_secs() >= limit
 }
 
-pub(crate) fn terminate() {
+pub fn terminate() {
     kill_ncurses();
 }
 
-pub(crate) fn read_isolated_data(isolate_file: Option<PathBuf>) -> Option<HashMap<String, models::IsolateData>> {
-    return match isolate_file {
-        Some(path) => {
-            let data = fs::read(path).expect("Couldn't read file");
-            let map: HashMap<String, models::IsolateData> = serde_json::from_str(
-                String::from_utf8(data).unwrap().as_str()).unwrap();
-            Some(map)
-        },
-        _ => {
-            None
-        }
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// pid of the program being waited for, 0 if there is none
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn on_signal(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    kill_child();
+}
+
+/// Kill the program being waited for, if any - only async-signal-safe calls may be made here
+pub fn kill_child() {
+    let pid = CHILD.load(Ordering::SeqCst);
+    if pid > 0 {
+        unsafe { libc::kill(pid, libc::SIGKILL); }
+    }
+}
+
+/// Have SIGINT and SIGTERM kill `pid`, until it is unset with `None`.
+/// The pid must not be reaped while it is set, or a reused pid may be killed
+pub fn set_child(pid: Option<libc::pid_t>) {
+    CHILD.store(pid.unwrap_or(0), Ordering::SeqCst);
+    // caught before the pid was set
+    if interrupted() {
+        kill_child();
     }
 }
 
-pub(crate) fn get_last_measurement_from(file: PathBuf) -> Vec<models::RAPLData> {
-    let mut rdr = csv::Reader::from_path(file).unwrap();
-    let zones = list_rapl();
-    let mut out: Vec<models::RAPLData> = vec![];
-    for res in rdr.deserialize() {
-        let r: models::RAPLData = res.unwrap();
-        out.push(r);
+/// Catch SIGINT and SIGTERM instead of dying with unwritten logs - the measurement loops stop once `interrupted`
+pub fn handle_signals() {
+    unsafe {
+        libc::signal(libc::SIGINT, on_signal as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, on_signal as *const () as libc::sighandler_t);
     }
+}
+
+pub fn interrupted() -> bool {
+    return INTERRUPTED.load(Ordering::SeqCst);
+}
 
-    let last = &out[out.len() - zones.len()..].to_vec();
+/// `Error::Interrupted` if SIGINT or SIGTERM was caught - call once the logs of the current run are written
+pub fn check_interrupted() -> Result<()> {
+    if interrupted() {
+        return Err(Error::Interrupted);
+    }
 
-    return last.to_owned()
+    return Ok(());
 }
 
-pub(crate) fn create_log_file_name(benchmark_name: String, tool: String, system_start_time: SystemTime) -> String {
-    let mut benchmark_name = benchmark_name;
-    if benchmark_name != "" {
-        benchmark_name = benchmark_name + "-";
+/// Isolation data of every zone of `source`, keyed by zone id
+pub fn read_isolated_data(source: &dyn EnergySource,
+                          isolate_file: Option<PathBuf>) -> Result<Option<HashMap<String, models::IsolateData>>> {
+    let path = match isolate_file {
+        Some(path) => path,
+        None => return Ok(None)
+    };
+
+    let data = fs::read_to_string(&path).map_err(|e| Error::read(path.display(), e))?;
+    let map: HashMap<String, models::IsolateData> = serde_json::from_str(&data)
+        .map_err(|e| Error::malformed(path.display(), e))?;
+    let zones = source.zones()?;
+    let map = migrate_isolated_data(&zones, map);
+
+    match zones.iter().find(|z| !map.contains_key(&z.id)) {
+        Some(zone) => return Err(Error::malformed(path.display(), format!(
+            "there is no data for zone {} - was it measured on another machine?", zone.id))),
+        None => {}
     }
 
-    let time = system_start_time.duration_since(UNIX_EPOCH).expect("Failed to check duration").as_secs();
-    return format!("{}{}-{}.csv", benchmark_name, tool, time);
+    return Ok(Some(map));
+}
+
+/// Isolation files from before zone ids are keyed by zone name, e.g. `core` rather than `package-1/core`.
+/// Such keys are re-keyed to the ids of all zones carrying that name.
+pub fn migrate_isolated_data(zones: &[models::RAPLZone],
+                             map: HashMap<String, models::IsolateData>) -> HashMap<String, models::IsolateData> {
+    let mut out = HashMap::new();
+
+    for (key, data) in map {
+        if zones.iter().any(|z| z.id == key) {
+            out.insert(key, data);
+            continue;
+        }
+
+        let ids: Vec<String> = zones.iter().filter(|z| z.name == key).map(|z| z.id.to_owned()).collect();
+        if ids.is_empty() {
+            out.insert(key, data);
+            continue;
+        }
+
+        eprintln!("Migrated isolation data of zone '{}' to {:?}", key, ids);
+        for id in ids {
+            out.entry(id).or_insert_with(|| data.to_owned());
+        }
+    }
+
+    return out;
+}
+
+pub fn get_last_measurement_from(file: PathBuf) -> Result<Vec<models::RAPLData>> {
+    return Ok(read_samples(file)?.pop().unwrap_or_default());
+}
+
+/// Read a .csv file written by the measurement tools, as samples of one row per zone
+pub fn read_samples(file: PathBuf) -> Result<Vec<Vec<models::RAPLData>>> {
+    let mut rdr = csv::ReaderBuilder::new().comment(Some(b'#')).from_path(&file).map_err(|e| csv_error(&file, e))?;
+    let rows = rdr.deserialize().collect::<std::result::Result<Vec<models::RAPLData>, csv::Error>>()
+        .map_err(|e| csv_error(&file, e))?;
+
+    return Ok(group_samples(rows));
+}
+
+/// Error reading the .csv file `file` - failures to open it are told apart from malformed rows
+pub fn csv_error(file: &Path, error: csv::Error) -> Error {
+    if !error.is_io_error() {
+        return Error::malformed(file.display(), error);
+    }
+
+    return match error.into_kind() {
+        csv::ErrorKind::Io(e) => Error::read(file.display(), e),
+        kind => Error::malformed(file.display(), format!("{:?}", kind))
+    };
 }
 
-pub(crate) fn get_cpu_temp() -> f64 {
-    let sensors = Command::new("sensors")
-        .stdout(Stdio::piped())
-        .spawn()
-        .unwrap();
+/// Group rows by run and sample id - rows from before schema 2 have neither,
+/// so a new sample starts whenever a zone repeats instead
+pub fn group_samples(rows: Vec<models::RAPLData>) -> Vec<Vec<models::RAPLData>> {
+    let mut out: Vec<Vec<models::RAPLData>> = vec![];
+
+    for row in rows {
+        let new_sample = match out.last() {
+            Some(sample) if row.run_id.is_empty() => sample.iter().any(|z| z.zone == row.zone),
+            Some(sample) => sample[0].run_id != row.run_id || sample[0].sample != row.sample,
+            None => true
+        };
+
+        if new_sample {
+            out.push(vec![row]);
+        } else {
+            out.last_mut().unwrap().push(row);
+        }
+    }
 
-    let grep = Command::new("grep")
-        .arg("Core")
-        .stdin(sensors.stdout.unwrap())
-        .stdout(Stdio::piped())
-        .output()
-        .unwrap();
+    return out;
+}
 
-    let output = String::from_utf8_lossy(&grep.stdout).to_string();
-    let split = output.split("\n");
+/// Id of the run started at `system_start_time` on this machine, e.g. `lab-3-1636984127501783`
+pub fn run_id(system_start_time: SystemTime) -> String {
+    let time = system_start_time.duration_since(UNIX_EPOCH).expect("Failed to check duration").as_micros();
+    return format!("{}-{}", hostname(), time);
+}
 
-    let re = Regex::new(r"\d+\.\d+").unwrap();
-    let mut core_count = 0;
-    let mut temp_total = 0.;
+pub fn hostname() -> String {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    return HOSTNAME.get_or_init(|| {
+        fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default().trim().to_string()
+    }).to_owned();
+}
 
-    for s in split {
-        if s == "" { continue }
+/// File to log the output of `tool` started at `system_start_time` to - inside the session directory, if any
+pub fn create_log_file_name(benchmark_name: String, tool: String, system_start_time: SystemTime) -> Result<String> {
+    return log_file_name(benchmark_name, tool, system_start_time, "csv");
+}
 
-        let core_temp = s.split("(").collect::<Vec<&str>>()[0];
+pub fn create_json_file_name(benchmark_name: String, tool: String, system_start_time: SystemTime) -> Result<String> {
+    return log_file_name(benchmark_name, tool, system_start_time, "json");
+}
 
-        let temp = re.find(core_temp).unwrap().as_str().parse::<f64>().unwrap();
+fn log_file_name(benchmark_name: String, tool: String, system_start_time: SystemTime, extension: &str) -> Result<String> {
+    match logger::session() {
+        Some(session) => return session.file_name(&benchmark_name, &tool, system_start_time, extension),
+        None => {}
+    }
 
-        core_count += 1;
-        temp_total += temp;
+    let mut benchmark_name = benchmark_name;
+    if !benchmark_name.is_empty() {
+        benchmark_name.push('-');
     }
 
-    let avg_temp = temp_total / core_count as f64;
+    let time = system_start_time.duration_since(UNIX_EPOCH).expect("Failed to check duration").as_secs();
+    return Ok(format!("{}{}-{}.{}", benchmark_name, tool, time, extension));
+}
+
+pub fn host_info() -> models::HostInfo {
+    let read = |path: &str| fs::read_to_string(path).unwrap_or_default().trim().to_string();
+    let cpuinfo = read("/proc/cpuinfo");
+    let cpu_model = cpuinfo.lines().find(|l| l.starts_with("model name"))
+        .and_then(|l| l.split(':').nth(1)).unwrap_or("").trim().to_string();
+
+    return models::HostInfo{
+        hostname: hostname(),
+        kernel: read("/proc/sys/kernel/osrelease"),
+        cpu_model,
+        cpus: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(0)
+    };
+}
 
-    return avg_temp;
+/// Read the sensors found by `thermal::init`
+pub fn read_temperatures() -> thermal::Reading {
+    return thermal::thermal().read();
 }
\ No newline at end of file
@@ -0,0 +1,116 @@
+use std::fmt;
+use std::io;
+
+// exit codes of the `raplrs` binary, following sysexits.h
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_DATA_ERROR: i32 = 65;
+pub const EXIT_NO_INPUT: i32 = 66;
+pub const EXIT_UNAVAILABLE: i32 = 69;
+pub const EXIT_CANT_CREATE: i32 = 73;
+pub const EXIT_IO_ERROR: i32 = 74;
+pub const EXIT_NO_PERMISSION: i32 = 77;
+/// SIGINT or SIGTERM ended the run, see `Error::Interrupted`
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Hint for files only root may read, e.g. `energy_uj` since Linux 5.10
+pub const READ_ACCESS_HINT: &str = "run as root or grant read access to it";
+/// Hint for the energy counters of the powercap and hwmon sources
+pub const ENERGY_ACCESS_HINT: &str = "run as root, or grant read access with `sudo raplrs setup-permissions`";
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading `path` is not permitted - `hint` tells how to get access
+    PermissionDenied{ path: String, hint: String },
+    /// An input file or directory that doesn't exist
+    NotFound{ path: String },
+    /// The energy source found no zones, e.g. because the RAPL driver isn't loaded
+    NoZones,
+    /// The energy source can't be used on this machine
+    SourceUnavailable{ source: String, reason: String },
+    /// An input file that can't be parsed
+    Malformed{ path: String, reason: String },
+    /// Any other failure reading `path`
+    Read{ path: String, error: io::Error },
+    /// Failure creating or writing the output `path`
+    Write{ path: String, error: io::Error },
+    /// A program to benchmark that couldn't be started or waited for
+    Program{ program: String, error: io::Error },
+    /// A step of `setup-permissions` that failed, e.g. writing the udev rule
+    Setup{ action: String, error: io::Error },
+    /// Invalid arguments
+    Usage(String),
+    /// SIGINT or SIGTERM ended the run, once the logs of the current run are written
+    Interrupted
+}
+
+impl Error {
+    /// Error reading `path`, telling missing files and lacking permissions apart
+    pub fn read<P: fmt::Display>(path: P, error: io::Error) -> Error {
+        return match error.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied{
+                path: path.to_string(),
+                hint: READ_ACCESS_HINT.to_string()
+            },
+            io::ErrorKind::NotFound => Error::NotFound{ path: path.to_string() },
+            _ => Error::Read{ path: path.to_string(), error }
+        };
+    }
+
+    pub fn write<P: fmt::Display>(path: P, error: io::Error) -> Error {
+        return Error::Write{ path: path.to_string(), error };
+    }
+
+    pub fn malformed<P: fmt::Display, R: fmt::Display>(path: P, reason: R) -> Error {
+        return Error::Malformed{ path: path.to_string(), reason: reason.to_string() };
+    }
+
+    pub fn program<P: fmt::Debug>(program: P, error: io::Error) -> Error {
+        return Error::Program{ program: format!("{:?}", program), error };
+    }
+
+    /// Replace the hint of a `PermissionDenied` error
+    pub fn with_hint(self, hint: &str) -> Error {
+        return match self {
+            Error::PermissionDenied{ path, .. } => Error::PermissionDenied{ path, hint: hint.to_string() },
+            e => e
+        };
+    }
+
+    /// Exit code of the `raplrs` binary for this error
+    pub fn exit_code(&self) -> i32 {
+        return match self {
+            Error::PermissionDenied{ .. } => EXIT_NO_PERMISSION,
+            Error::NotFound{ .. } | Error::Program{ .. } => EXIT_NO_INPUT,
+            Error::NoZones | Error::SourceUnavailable{ .. } => EXIT_UNAVAILABLE,
+            Error::Malformed{ .. } => EXIT_DATA_ERROR,
+            Error::Read{ .. } => EXIT_IO_ERROR,
+            Error::Write{ .. } | Error::Setup{ .. } => EXIT_CANT_CREATE,
+            Error::Usage(_) => EXIT_USAGE,
+            Error::Interrupted => EXIT_INTERRUPTED
+        };
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Error::PermissionDenied{ path, hint } => write!(f, "Permission denied reading {} - {}", path, hint),
+            Error::NotFound{ path } => write!(f, "{} doesn't exist", path),
+            Error::NoZones => write!(f, "No RAPL zones found - check that the intel_rapl or amd_energy module is loaded, \
+                                         or pick another --source"),
+            Error::SourceUnavailable{ source, reason } => write!(f, "The {} source is unavailable: {}", source, reason),
+            Error::Malformed{ path, reason } => write!(f, "Couldn't parse {}: {}", path, reason),
+            Error::Read{ path, error } => write!(f, "Couldn't read {}: {}", path, error),
+            Error::Write{ path, error } => write!(f, "Couldn't write {}: {} - check that the output directory is writable",
+                                                  path, error),
+            Error::Program{ program, error } => write!(f, "Couldn't run {}: {}", program, error),
+            Error::Setup{ action, error } => write!(f, "Couldn't {}: {}", action, error),
+            Error::Usage(message) => write!(f, "{}", message),
+            Error::Interrupted => write!(f, "Interrupted")
+        };
+    }
+}
+
+impl std::error::Error for Error {}
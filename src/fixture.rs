@@ -0,0 +1,90 @@
+use crate::error::{Error, Result};
+use crate::models;
+use crate::sources;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A fake powercap tree whose `energy_uj` files follow a scripted sequence.
+///
+/// Packages become `intel-rapl:N` (or `<control_type>:N`), their subzones `intel-rapl:N:M` and so on -
+/// exactly like the kernel lays them out.
+pub struct FakePowercap {
+    zones: Vec<(PathBuf, Vec<u64>)>,
+    step: usize
+}
+
+impl FakePowercap {
+    pub fn create(sysfs_root: &Path, packages: &Vec<models::FixtureZone>) -> Result<FakePowercap> {
+        let base_path = sysfs_root.join(sources::POWERCAP_PATH);
+        let mut zones = vec![];
+        let mut indices: HashMap<String, usize> = HashMap::new();
+
+        for pkg in packages {
+            let control_type = pkg.control_type.to_owned().unwrap_or_else(|| "intel-rapl".to_string());
+            let index = indices.entry(control_type.to_owned()).or_insert(0);
+            let pkg_dir = format!("{}:{}", control_type, index);
+            *index += 1;
+
+            create_zone(&base_path.join(&control_type), &pkg_dir, pkg, &mut zones)?;
+        }
+
+        return Ok(FakePowercap{ zones, step: 0 });
+    }
+
+    /// Amount of steps in the longest scripted sequence
+    pub fn steps(&self) -> usize {
+        return self.zones.iter().map(|(_, seq)| seq.len()).max().unwrap_or(0);
+    }
+
+    /// Advance every zone to its next scripted reading - zones with shorter sequences keep their last value.
+    /// Returns false once all sequences are exhausted.
+    pub fn step(&mut self) -> Result<bool> {
+        if self.step + 1 >= self.steps() {
+            return Ok(false);
+        }
+
+        self.step += 1;
+        for (path, seq) in &self.zones {
+            if self.step < seq.len() {
+                write_file(&path.join("energy_uj"), seq[self.step])?;
+            }
+        }
+
+        return Ok(true);
+    }
+}
+
+/// Create the zone `dir_name` in `parent_path`, followed by its subzones `<dir_name>:N`
+fn create_zone(parent_path: &Path, dir_name: &str, zone: &models::FixtureZone,
+               zones: &mut Vec<(PathBuf, Vec<u64>)>) -> Result<()> {
+    let path = parent_path.join(dir_name);
+    write_zone(&path, zone)?;
+    zones.push((path.to_owned(), zone.energy_uj.to_owned()));
+
+    for (i, sub) in zone.subzones.iter().enumerate() {
+        create_zone(&path, &format!("{}:{}", dir_name, i), sub, zones)?;
+    }
+
+    return Ok(());
+}
+
+fn write_zone(path: &Path, zone: &models::FixtureZone) -> Result<()> {
+    fs::create_dir_all(path).map_err(|e| Error::write(path.display(), e))?;
+    fs::write(path.join("name"), format!("{}\n", zone.name)).map_err(|e| Error::write(path.join("name").display(), e))?;
+    write_file(&path.join("max_energy_range_uj"), zone.max_energy_range_uj)?;
+    write_file(&path.join("enabled"), 1)?;
+    write_file(&path.join("energy_uj"), *zone.energy_uj.first().unwrap_or(&0))?;
+
+    return Ok(());
+}
+
+/// Readers polling the tree must never see a half-written file, so values are swapped in by renaming
+fn write_file(path: &Path, value: u64) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, format!("{}\n", value)).map_err(|e| Error::write(tmp_path.display(), e))?;
+    fs::rename(&tmp_path, path).map_err(|e| Error::write(path.display(), e))?;
+
+    return Ok(());
+}
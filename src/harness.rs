@@ -0,0 +1,194 @@
+use crate::common;
+use crate::error::Result;
+use crate::logger;
+use crate::meter::Meter;
+use crate::models;
+use crate::stats;
+use crate::thermal;
+
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// RAPL counters update roughly every millisecond - samples need to be considerably longer than that
+pub const RAPL_UPDATE_INTERVAL: Duration = Duration::from_millis(1);
+
+/// In-process energy micro-benchmark harness.
+///
+/// The benchmarked function is run repeatedly during a warm-up period, which also estimates its run time.
+/// Every sample then runs the function as many times as is needed to last at least `min_sample_time`,
+/// and the consumption of each sample is divided by its amount of iterations.
+///
+/// ```no_run
+/// # fn main() -> Result<(), raplrs::Error> {
+/// let meter = raplrs::Meter::detect()?;
+/// let report = raplrs::harness::Harness::new(&meter)
+///     .samples(50)
+///     .bench("sum", || (0..10_000u64).sum::<u64>())?;
+/// report.print();
+/// # Ok(())
+/// # }
+/// ```
+pub struct Harness<'a> {
+    meter: &'a Meter,
+    warm_up: Duration,
+    samples: usize,
+    min_sample_time: Duration
+}
+
+/// Consumption of a single sample, per iteration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchSample {
+    /// Run time of an iteration (s)
+    pub time: f64,
+    /// Energy of an iteration per zone id (J)
+    pub power_j: HashMap<String, f64>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub name: String,
+    /// Iterations per sample
+    pub iterations: u64,
+    pub samples: Vec<BenchSample>,
+    /// Run time per iteration (s)
+    pub time: stats::Summary,
+    /// Energy per iteration, per zone id (J)
+    pub zones: HashMap<String, stats::Summary>,
+    #[serde(skip)]
+    zone_order: Vec<String>,
+    #[serde(skip)]
+    temperatures: thermal::Reading
+}
+
+impl<'a> Harness<'a> {
+    pub fn new(meter: &'a Meter) -> Harness<'a> {
+        return Harness{
+            meter,
+            warm_up: Duration::from_secs(1),
+            samples: 30,
+            min_sample_time: RAPL_UPDATE_INTERVAL * 100
+        };
+    }
+
+    pub fn warm_up(self, warm_up: Duration) -> Harness<'a> {
+        return Harness{ warm_up, ..self };
+    }
+
+    pub fn samples(self, samples: usize) -> Harness<'a> {
+        return Harness{ samples: samples.max(1), ..self };
+    }
+
+    /// Shortest duration of a sample - raised to the RAPL update interval if lower
+    pub fn min_sample_time(self, min_sample_time: Duration) -> Harness<'a> {
+        return Harness{ min_sample_time: min_sample_time.max(RAPL_UPDATE_INTERVAL), ..self };
+    }
+
+    pub fn bench<R, F: FnMut() -> R>(&self, name: &str, mut f: F) -> Result<BenchReport> {
+        let iterations = self.warm_up_iterations(&mut f);
+        let mut samples = vec![];
+        let mut zone_order = vec![];
+        let mut temperatures = thermal::Reading::default();
+
+        for _ in 0..self.samples {
+            let mut session = self.meter.start()?;
+            let start_time = Instant::now();
+            for _ in 0..iterations {
+                black_box(f());
+            }
+            let time = start_time.elapsed().as_secs_f64();
+            session.sample()?;
+            temperatures = common::read_temperatures();
+
+            let data = session.data();
+            zone_order = data.iter().map(|z| z.zone.to_owned()).collect();
+
+            samples.push(BenchSample{
+                time: time / iterations as f64,
+                power_j: data.iter().map(|z| (z.zone.to_owned(), z.power_j / iterations as f64)).collect()
+            });
+        }
+
+        let times: Vec<f64> = samples.iter().map(|s| s.time).collect();
+        let zones = zone_order.iter().map(|zone| {
+            let values: Vec<f64> = samples.iter().map(|s| s.power_j[zone]).collect();
+            (zone.to_owned(), stats::Summary::of(&values))
+        }).collect();
+
+        return Ok(BenchReport{
+            name: name.to_string(),
+            iterations,
+            time: stats::Summary::of(&times),
+            samples,
+            zones,
+            zone_order,
+            temperatures
+        });
+    }
+
+    /// Run `f` for the warm-up period and determine the iterations needed per sample
+    fn warm_up_iterations<R, F: FnMut() -> R>(&self, f: &mut F) -> u64 {
+        let start_time = Instant::now();
+        let mut runs: u64 = 0;
+
+        while runs == 0 || start_time.elapsed() < self.warm_up {
+            black_box(f());
+            runs += 1;
+        }
+
+        let per_iteration = start_time.elapsed().as_secs_f64() / runs as f64;
+        return (self.min_sample_time.as_secs_f64() / per_iteration).ceil().max(1.) as u64;
+    }
+}
+
+impl BenchReport {
+    /// Mean consumption per iteration, in the shape of the measurement tools' result lines
+    pub fn summary(&self) -> Vec<models::RAPLData> {
+        let mut out = vec![];
+
+        for zone in &self.zone_order {
+            let power_j = self.zones[zone].mean;
+            let mut data = models::RAPLData{
+                path: "".to_string(),
+                run_id: "".to_string(),
+                sample: self.samples.len() as u64,
+                timestamp: 0.,
+                zone: zone.to_owned(),
+                time_elapsed: self.time.mean,
+                power_j,
+                watts: power_j / self.time.mean,
+                watts_since_last: 0.,
+                start_power: 0.,
+                prev_power: 0.,
+                prev_power_reading: 0.,
+                package_temp: None,
+                core_temp_max: None,
+                core_temp_avg: None
+            };
+            self.temperatures.apply(&mut data);
+            out.push(data);
+        }
+
+        return out;
+    }
+
+    /// Write the report to `<name>-micro-STAMP.json` for later comparison - numbered in the output directory
+    /// of a running session, like the files of the other tools. Returns the file name
+    pub fn save(&self) -> Result<String> {
+        return logger::log_bench_report(self);
+    }
+
+    /// Print the mean consumption per iteration, followed by the spread of each zone
+    pub fn print(&self) {
+        println!("{}: {} samples of {} iterations, per iteration:", self.name, self.samples.len(), self.iterations);
+        print_headers!();
+        print_result_line!(&self.summary());
+        println!();
+
+        for zone in &self.zone_order {
+            let s = &self.zones[zone];
+            println!("{}: {:.9} J median, {:.9} J std dev, [{:.9} J, {:.9} J]", zone, s.median, s.std_dev, s.min, s.max);
+        }
+    }
+}
@@ -0,0 +1,30 @@
+//! RAPL measurement library - the engine behind the `raplrs` tool.
+//!
+//! [`meter::Meter`] is the entry point for measuring from Rust, [`region`](mod@region) measures regions of code and [`harness`]
+//! micro benchmarks. The hidden modules back the CLI and are not part of the API.
+
+// explicit returns and matches on a single pattern are the house style
+#![allow(clippy::needless_return, clippy::single_match)]
+
+// declared first, so its macros are visible to the modules below
+#[doc(hidden)] #[macro_use] pub mod common;
+pub mod error;
+pub mod models;
+pub mod sources;
+pub mod meter;
+pub mod region;
+pub mod stats;
+pub mod harness;
+
+#[doc(hidden)] pub mod tools;
+#[doc(hidden)] pub mod logger;
+#[doc(hidden)] pub mod task;
+#[doc(hidden)] pub mod thermal;
+#[doc(hidden)] pub mod fixture;
+#[doc(hidden)] pub mod permissions;
+#[doc(hidden)] pub mod regression;
+
+pub use error::Error;
+pub use meter::{Meter, Session};
+pub use region::{measure, region, try_measure, try_region, EnergyGuard, Region};
+
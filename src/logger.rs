@@ -1,44 +1,246 @@
 use crate::models;
 use crate::common;
+use crate::error::{Error, Result};
+use crate::harness;
 
 use csv;
+use serde::Serialize;
 use serde_json;
 use std::collections::HashMap;
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::fs::OpenOptions;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::fs::{File, OpenOptions};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::os::unix::fs::PermissionsExt;
+use std::sync::{Mutex, OnceLock};
 
-pub(crate) fn log_poll_result(system_start_time: SystemTime, tool: String, zone: models::RAPLData,
-                              benchmark_name: String) {
-    let file_name = common::create_log_file_name(benchmark_name, tool, system_start_time);
+static SESSION: OnceLock<LogSession> = OnceLock::new();
 
-    if Path::new(file_name.to_owned().as_str()).exists() {
-        let file = OpenOptions::new().write(true).append(true).open(file_name.to_owned()).unwrap();
+/// How long rows may sit in the buffer of a `CsvSink`
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
 
-        let mut wtr = csv::WriterBuilder::default().has_headers(false).from_writer(file);
-        wtr.serialize(zone).expect("Failed to write to file");
+/// Output directory of one invocation - every log file of the process is created in it,
+/// under a unique name, and listed in its `manifest.json`
+pub struct LogSession {
+    pub dir: PathBuf,
+    manifest: Mutex<models::SessionManifest>,
+    /// File of each (benchmark name, tool, start time, extension) logged so far
+    files: Mutex<HashMap<(String, String, SystemTime, String), String>>
+}
+
+impl LogSession {
+    /// Unique file for the output of `tool` started at `start_time`, numbered per benchmark name and tool,
+    /// e.g. `fib-benchmark-2.csv` for the second iteration
+    pub fn file_name(&self, benchmark_name: &str, tool: &str, start_time: SystemTime, extension: &str) -> Result<String> {
+        let mut files = self.files.lock().unwrap();
+        let key = (benchmark_name.to_string(), tool.to_string(), start_time, extension.to_string());
+        match files.get(&key) {
+            Some(file) => return Ok(file.to_owned()),
+            None => {}
+        }
+
+        let n = files.keys().filter(|(b, t, _, e)| b == benchmark_name && t == tool && e == extension).count() + 1;
+        let prefix = if benchmark_name.is_empty() { "".to_string() } else { format!("{}-", benchmark_name) };
+        let file = format!("{}{}-{}.{}", prefix, tool, n, extension);
+        let path = self.path(&file);
+        files.insert(key, path.to_owned());
+
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.files.push(file);
+        self.write_manifest(&manifest)?;
+
+        return Ok(path);
+    }
+
+    pub fn path(&self, file: &str) -> String {
+        return self.dir.join(file).display().to_string();
+    }
+
+    /// Record the end time in the manifest
+    pub fn finish(&self) -> Result<()> {
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.end_time = Some(unix_time(SystemTime::now()));
+        return self.write_manifest(&manifest);
+    }
+
+    fn write_manifest(&self, manifest: &models::SessionManifest) -> Result<()> {
+        let path = self.dir.join("manifest.json");
+        let json = serde_json::to_string_pretty(manifest).unwrap();
+        return fs::write(&path, json).map_err(|e| Error::write(path.display(), e));
+    }
+}
+
+/// Create `<output_dir>/<name>-<tool>-STAMP` and log every file of this process into it.
+/// The directory is handed to `owner`, i.e. the user running the benchmarks, so they can clean it up without root
+pub fn start_session(output_dir: &Path, manifest: models::SessionManifest,
+                     owner: Option<&models::RunAs>) -> Result<&'static LogSession> {
+    if SESSION.get().is_some() {
+        return Err(already_started());
+    }
+
+    let prefix = if manifest.name.is_empty() { "".to_string() } else { format!("{}-", manifest.name) };
+    let base = format!("{}{}-{}", prefix, manifest.tool, manifest.start_time as u64);
+    let mut dir = output_dir.join(&base);
+    let mut i = 1;
+    while dir.exists() {
+        dir = output_dir.join(format!("{}-{}", base, i));
+        i += 1;
+    }
+
+    fs::create_dir_all(&dir).map_err(|e| Error::write(dir.display(), e))?;
+    let mode = match owner {
+        Some(user) => {
+            std::os::unix::fs::chown(&dir, Some(user.uid), Some(user.gid)).map_err(|e| Error::write(dir.display(), e))?;
+            0o775
+        },
+        None => 0o755
+    };
+    fs::set_permissions(&dir, fs::Permissions::from_mode(mode)).map_err(|e| Error::write(dir.display(), e))?;
+
+    let session = LogSession{ dir, manifest: Mutex::new(manifest), files: Mutex::new(HashMap::new()) };
+    session.write_manifest(&session.manifest.lock().unwrap())?;
+    // started by another thread in the meantime
+    SESSION.set(session).map_err(|_| already_started())?;
+
+    return SESSION.get().ok_or_else(already_started);
+}
+
+fn already_started() -> Error {
+    return Error::Usage("A log session was already started by this process, there is one per process".to_string());
+}
+
+pub fn session() -> Option<&'static LogSession> {
+    return SESSION.get();
+}
+
+pub fn unix_time(time: SystemTime) -> f64 {
+    return time.duration_since(UNIX_EPOCH).expect("Failed to check duration").as_secs_f64();
+}
+
+/// The .csv file of the polls of one run, kept open by the measurement loop for as long as it runs.
+/// Rows are buffered, and written every `FLUSH_INTERVAL` and when the sink is dropped
+pub struct CsvSink {
+    pub file_name: String,
+    /// Id of the run, see `common::run_id`
+    pub run_id: String,
+    wtr: csv::Writer<File>,
+    last_flush: Instant
+}
 
-        let mut perms = fs::metadata(file_name.to_owned()).unwrap().permissions();
-        perms.set_mode(0o666);
-        fs::set_permissions(file_name.to_string(), perms).expect("Failed to set permissions for file");
-    } else {
-        let file = OpenOptions::new().write(true).create(true).open(file_name).unwrap();
+impl CsvSink {
+    /// Open the log file of `tool` started at `system_start_time`, appending if it exists
+    pub fn create(system_start_time: SystemTime, tool: String, benchmark_name: String) -> Result<CsvSink> {
+        let file_name = common::create_log_file_name(benchmark_name, tool, system_start_time)?;
+        let exists = Path::new(file_name.as_str()).exists();
+        let write_error = |e| Error::write(&file_name, e);
+        let mut file = OpenOptions::new().create(true).append(true).open(&file_name).map_err(write_error)?;
 
-        let mut wtr = csv::Writer::from_writer(file);
-        wtr.serialize(zone).expect("Failed to write to file");
+        if !exists {
+            writeln!(file, "# raplrs csv schema {}", common::CSV_SCHEMA_VERSION).map_err(write_error)?;
+            fs::set_permissions(&file_name, fs::Permissions::from_mode(0o666)).map_err(write_error)?;
+        }
+
+        return Ok(CsvSink{
+            run_id: common::run_id(system_start_time),
+            wtr: csv::WriterBuilder::default().has_headers(!exists).from_writer(file),
+            last_flush: Instant::now(),
+            file_name
+        });
+    }
+
+    pub fn write(&mut self, zone: &models::RAPLData) -> Result<()> {
+        self.wtr.serialize(zone).map_err(|e| Error::write(&self.file_name, e.into()))?;
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+
+        return Ok(());
     }
 
+    pub fn flush(&mut self) -> Result<()> {
+        self.wtr.flush().map_err(|e| Error::write(&self.file_name, e))?;
+        self.last_flush = Instant::now();
+
+        return Ok(());
+    }
 }
 
-pub(crate) fn log_isolate_data(map: HashMap<String, models::IsolateData>) {
-    let file_name = format!("isolate-data-{}.json", SystemTime::now()
-        .duration_since(UNIX_EPOCH).expect("Failed to check duration").as_secs_f64());
-    let mut file = OpenOptions::new().write(true).create(true).open(file_name).unwrap();
-    let json = serde_json::to_string_pretty(&map).unwrap();
+impl Drop for CsvSink {
+    fn drop(&mut self) {
+        let _ = self.wtr.flush();
+    }
+}
+
+pub fn log_isolate_data(map: HashMap<String, models::IsolateData>) -> Result<String> {
+    let file_name = common::create_json_file_name("".to_string(), "isolate-data".to_string(), SystemTime::now())?;
+    write_json(&file_name, &map)?;
+    return Ok(file_name);
+}
+
+pub fn log_bench_report(report: &harness::BenchReport) -> Result<String> {
+    let file_name = common::create_json_file_name(report.name.to_owned(), "micro".to_string(), SystemTime::now())?;
+    write_json(&file_name, report)?;
+    return Ok(file_name);
+}
+
+pub fn log_benchmark_summary(summary: &models::BenchmarkSummary, system_start_time: SystemTime) -> Result<String> {
+    let file_name = common::create_json_file_name(summary.name.to_owned(), "benchmark-summary".to_string(), system_start_time)?;
+    write_json(&file_name, summary)?;
+    return Ok(file_name);
+}
 
-    file.write(json.as_bytes()).expect("Failed to write isolation data to file");
-    drop(file);
-}
\ No newline at end of file
+pub fn log_comparison_summary(summary: &models::ComparisonSummary, system_start_time: SystemTime) -> Result<String> {
+    let file_name = common::create_json_file_name(summary.name.to_owned(), "compare".to_string(), system_start_time)?;
+    write_json(&file_name, summary)?;
+    return Ok(file_name);
+}
+
+pub fn log_suite_summary(summary: &models::SuiteSummary, system_start_time: SystemTime) -> Result<String> {
+    let file_name = common::create_json_file_name(summary.name.to_owned(), "suite-summary".to_string(), system_start_time)?;
+    write_json(&file_name, summary)?;
+    return Ok(file_name);
+}
+
+/// Write one row per sweep combination and zone, with a column per parameter
+pub fn log_sweep_results(name: String, axes: &[models::SweepAxis], points: &[models::SweepPoint],
+                         system_start_time: SystemTime) -> Result<String> {
+    let file_name = common::create_log_file_name(name, "sweep".to_string(), system_start_time)?;
+    let write_error = |e: csv::Error| Error::write(&file_name, e.into());
+    let mut wtr = csv::Writer::from_path(&file_name).map_err(write_error)?;
+
+    let mut headers: Vec<String> = axes.iter().map(|a| a.name.to_owned()).collect();
+    for h in ["zone", "runs", "power_j", "power_j_std_dev", "power_j_ci_low", "power_j_ci_high", "time_elapsed", "watts"] {
+        headers.push(h.to_string());
+    }
+    wtr.write_record(&headers).map_err(write_error)?;
+
+    for point in points {
+        for zone in &point.summary.zones {
+            let mut record: Vec<String> = point.params.iter().map(|(_, v)| v.to_owned()).collect();
+            record.push(zone.zone.to_owned());
+            record.push(point.summary.runs.to_string());
+            for v in [zone.power_j.mean, zone.power_j.std_dev, zone.power_j.ci_low, zone.power_j.ci_high,
+                      zone.time_elapsed.mean, zone.watts.mean] {
+                record.push(v.to_string());
+            }
+            wtr.write_record(&record).map_err(write_error)?;
+        }
+    }
+
+    wtr.flush().map_err(|e| Error::write(&file_name, e))?;
+    return Ok(file_name);
+}
+
+pub fn log_scaling_analysis(name: String, analyses: &Vec<models::ScalingAnalysis>,
+                            system_start_time: SystemTime) -> Result<String> {
+    let file_name = common::create_json_file_name(name, "analyze".to_string(), system_start_time)?;
+    write_json(&file_name, analyses)?;
+    return Ok(file_name);
+}
+
+fn write_json<T: Serialize + ?Sized>(file_name: &str, value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value).unwrap();
+    return fs::write(file_name, json).map_err(|e| Error::write(file_name, e));
+}
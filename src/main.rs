@@ -1,10 +1,9 @@
-#[macro_use] mod common;
-mod tools;
-mod models;
-mod logger;
-mod task;
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use raplrs::{common, error, logger, models, permissions, sources, thermal, tools};
 
 use structopt::StructOpt;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::SystemTime;
 
@@ -27,6 +26,24 @@ struct Cli {
     /// Idle data to isolate measurements from - see README.md for details
     #[structopt(short = "i", long = "isolate-from", parse(from_os_str))]
     isolate_file: Option<PathBuf>,
+    /// Root of the sysfs tree to read zones from - e.g. a tree created by `fixture`
+    #[structopt(long = "sysfs-root", default_value = "/sys", parse(from_os_str))]
+    sysfs_root: PathBuf,
+    /// Where to read energy counters from
+    #[structopt(long = "source", default_value = "auto", possible_values = sources::SOURCES)]
+    source: String,
+    /// Directory containing the per-cpu msr device files, used by the msr source
+    #[structopt(long = "msr-dir", default_value = "/dev/cpu", parse(from_os_str))]
+    msr_dir: PathBuf,
+    /// User to run benchmarked programs as - defaults to the user invoking sudo
+    #[structopt(long = "user")]
+    user: Option<String>,
+    /// Group to run benchmarked programs as - defaults to the group of the user invoking sudo
+    #[structopt(long = "group")]
+    group: Option<String>,
+    /// Directory to create the output directory of this run in
+    #[structopt(short = "o", long = "output-dir", default_value = ".", parse(from_os_str))]
+    output_dir: PathBuf,
     /// Tool to use
     #[structopt(subcommand)]
     tool: Tool
@@ -46,12 +63,89 @@ enum Tool {
         program: PathBuf,
         /// Args for <program>
         args: Vec<String>,
-        /// Amount of times to run benchmark
+        /// Amount of times to run benchmark - the minimum amount with --target-cv
+        #[structopt(short = "n", default_value = "1")]
+        n: u64,
+        /// Interval between benchmark runs in seconds
+        #[structopt(short = "i", long = "interval", default_value = "0")]
+        interval: u64,
+        /// Amount of unmeasured runs before the measured ones
+        #[structopt(short = "w", long = "warmup", default_value = "0")]
+        warmup: u64,
+        /// Repeat until the coefficient of variation of package energy is at most this, e.g. 0.02
+        #[structopt(long = "target-cv")]
+        target_cv: Option<f64>,
+        /// Maximum amount of runs with --target-cv
+        #[structopt(long = "max-runs", default_value = "100")]
+        max_runs: u64,
+        /// Stop repeating after this many seconds
+        #[structopt(long = "max-time")]
+        max_time: Option<u64>
+    },
+    #[structopt(about = "Compare the power consumption of two or more oneshot programs")]
+    Compare {
+        /// Commands to compare, e.g. "bash fib.sh 30" - the first is the baseline
+        #[structopt(required = true, min_values = 2)]
+        commands: Vec<String>,
+        /// Amount of times to run each command
+        #[structopt(short = "n", default_value = "10")]
+        n: u64,
+        /// Interval between benchmark runs in seconds
+        #[structopt(short = "i", long = "interval", default_value = "0")]
+        interval: u64,
+        /// Amount of unmeasured rounds before the measured ones
+        #[structopt(short = "w", long = "warmup", default_value = "0")]
+        warmup: u64,
+        /// Seed for the randomized run order
+        #[structopt(long = "seed")]
+        seed: Option<u64>
+    },
+    #[structopt(about = "Benchmark a oneshot script for every combination of parameter values")]
+    Sweep {
+        /// Parameter and its values, e.g. n=10..40:10 or threads=1,2,4 - substituted for {n} in <program> and <args>
+        #[structopt(short = "p", long = "param", required = true, number_of_values = 1)]
+        params: Vec<String>,
+        /// Benchmark requires <runner> to execute
+        #[structopt(short = "r", long = "runner", parse(from_os_str))]
+        runner: Option<PathBuf>,
+        /// Benchmark program
+        #[structopt(parse(from_os_str))]
+        program: PathBuf,
+        /// Args for <program>
+        args: Vec<String>,
+        /// Amount of times to run each combination
         #[structopt(short = "n", default_value = "1")]
         n: u64,
         /// Interval between benchmark runs in seconds
         #[structopt(short = "i", long = "interval", default_value = "0")]
-        interval: u64
+        interval: u64,
+        /// Amount of unmeasured runs before the measured ones, per combination
+        #[structopt(short = "w", long = "warmup", default_value = "0")]
+        warmup: u64
+    },
+    #[structopt(about = "Fit models of energy versus a parameter to the results of sweep")]
+    Analyze {
+        /// .csv file written by sweep
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Parameter to fit energy against - may be left out if there is only one
+        #[structopt(short = "x", long = "param")]
+        x: Option<String>,
+        /// Only fit this zone
+        #[structopt(short = "z", long = "zone")]
+        zone: Option<String>,
+        /// Degree of the polynomial fit
+        #[structopt(long = "degree", default_value = "2")]
+        degree: usize,
+        /// Predict the energy at this parameter value
+        #[structopt(short = "p", long = "predict", number_of_values = 1)]
+        predict: Vec<f64>
+    },
+    #[structopt(about = "Run the benchmarks of a TOML manifest, collecting the results in one directory")]
+    Suite {
+        /// Manifest listing the benchmarks - see README.md for details
+        #[structopt(parse(from_os_str))]
+        manifest: PathBuf
     },
     #[structopt(about = "Measure power consumption of an interactive application")]
     BenchmarkInt {
@@ -82,46 +176,237 @@ enum Tool {
         measure: u64,
         /// Generate isolation data based on input .csv file
         #[structopt(short = "g", long = "generate")]
-        file: Option<PathBuf>
+        file: Option<PathBuf>,
+        /// Re-key an isolation data file keyed by zone names to zone ids
+        #[structopt(long = "migrate", parse(from_os_str))]
+        migrate: Option<PathBuf>
+    },
+    #[structopt(about = "Check which energy sources and temperature sensors the current user can read")]
+    Doctor {},
+    #[structopt(about = "Let a group read the energy counters, so raplrs and the benchmarks can run without root")]
+    SetupPermissions {
+        /// Group to grant read access to - created if it doesn't exist
+        #[structopt(short = "g", long = "group", default_value = permissions::DEFAULT_GROUP)]
+        group: String,
+        /// User to add to the group - defaults to the user invoking sudo
+        #[structopt(short = "u", long = "user")]
+        user: Option<String>,
+        /// Also let the group open system-wide perf events, by granting CAP_PERFMON to this binary and restricting it to the group
+        #[structopt(long = "perf")]
+        perf: bool,
+        /// Only print the changes
+        #[structopt(long = "dry-run")]
+        dry_run: bool
+    },
+    #[structopt(about = "Create a fake powercap tree replaying scripted energy readings, one step per delay")]
+    Fixture {
+        /// JSON file describing the packages, their subzones and energy_uj sequences
+        #[structopt(parse(from_os_str))]
+        spec: PathBuf,
+        /// Directory to use as sysfs root for the fake tree
+        #[structopt(parse(from_os_str))]
+        root: PathBuf
     }
 }
 
 fn main() {
     let system_start_time = SystemTime::now();
     let args_ = Cli::from_args();
+    common::handle_signals();
+
+    // a panic mid-measurement must not leave the terminal in ncurses mode
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        common::restore_terminal();
+        default_hook(info);
+    }));
+
+    match run(args_, system_start_time) {
+        Ok(_) => {},
+        Err(e) => {
+            common::restore_terminal();
+            match logger::session() {
+                Some(session) => {
+                    let _ = session.finish();
+                },
+                None => {}
+            }
+            match e {
+                error::Error::Interrupted => println!("{}", e),
+                _ => eprintln!("Error: {}", e)
+            }
+            std::process::exit(e.exit_code());
+        }
+    }
+}
+
+fn run(args_: Cli, system_start_time: SystemTime) -> error::Result<()> {
+    // these diagnose and fix what keeps the energy source from being created, so they go without one
+    match args_.tool {
+        Tool::Doctor { } => {
+            return tools::doctor(args_.sysfs_root, args_.msr_dir);
+        },
+        Tool::SetupPermissions { group, user, perf, dry_run } => {
+            return tools::setup_permissions(args_.sysfs_root, group, user, perf, dry_run);
+        },
+        _ => {}
+    }
+
     let name = args_.name.unwrap_or(String::from(""));
+    // the measurement keeps the privileges of raplrs, only the benchmarked programs drop them
+    let run_as = permissions::run_as(args_.user, args_.group)?;
+    // tools that only read and write files go without an energy source, so they work on any machine
+    let source_name = match needs_source(&args_.tool) {
+        true => Some(match args_.source.as_str() {
+            "auto" => sources::detect_source(&args_.sysfs_root, &args_.msr_dir).to_string(),
+            s => s.to_string()
+        }),
+        false => None
+    };
+    let source = match &source_name {
+        Some(source_name) => {
+            let source = sources::create_source(source_name, &args_.sysfs_root, &args_.msr_dir)?;
+            thermal::init(&args_.sysfs_root);
+            Some(source)
+        },
+        None => None
+    };
+    let session = match session_tool(&args_.tool) {
+        Some(tool) => {
+            let session_name = match &args_.tool {
+                Tool::Suite { manifest } => tools::suite_name(manifest, name.to_owned())?,
+                _ => name.to_owned()
+            };
+            Some(logger::start_session(&args_.output_dir, models::SessionManifest{
+                tool: tool.to_string(),
+                name: session_name,
+                command: std::env::args().collect(),
+                source: source_name,
+                isolate_file: args_.isolate_file.as_ref().map(|f| f.display().to_string()),
+                run_as: run_as.as_ref().map(|u| u.name.to_owned()),
+                start_time: logger::unix_time(system_start_time),
+                end_time: None,
+                host: common::host_info(),
+                files: vec![]
+            }, run_as.as_ref())?)
+        },
+        None => None
+    };
+
+    let source = match source {
+        Some(source) => source,
+        None => {
+            match args_.tool {
+                Tool::Analyze { file, x, zone, degree, predict } => {
+                    tools::analyze(file, x, zone, degree, predict, name)?;
+                },
+                Tool::Isolate { file: Some(path), .. } => {
+                    // generate data
+                    tools::generate_isolate_data(path)?;
+                },
+                Tool::PrettyPrint { file } => {
+                    tools::pretty_print(file)?;
+                },
+                Tool::Fixture { spec, root } => {
+                    tools::fixture(spec, root, args_.delay)?;
+                },
+                _ => {}
+            }
+            return finish_session(session);
+        }
+    };
+
     match args_.tool {
         Tool::Live { } => {
             common::setup_ncurses();
-            tools::live_measurement(args_.delay, system_start_time, args_.run_time_limit, name);
+            tools::live_measurement(&source, args_.delay, system_start_time, args_.run_time_limit, name)?;
+        },
+        Tool::Benchmark { runner, program, args, n, interval, warmup, target_cv, max_runs, max_time } => {
+            let program = models::Program{
+                runner, program, args, env: HashMap::new(), working_dir: None, timeout: None, run_as: run_as.to_owned()
+            };
+            let options = models::BenchmarkOptions{ n, interval, warmup, target_cv, max_runs, max_time };
+            tools::do_benchmarks(&source, args_.delay, &program, name, args_.isolate_file, &options)?;
         },
-        Tool::Benchmark { runner, program, args, n, interval } => {
-            tools::do_benchmarks(args_.delay, runner, program, args, n, name, args_.isolate_file, interval);
+        Tool::Compare { commands, n, interval, warmup, seed } => {
+            let options = models::BenchmarkOptions{ n, interval, warmup, target_cv: None, max_runs: n, max_time: None };
+            tools::compare(&source, args_.delay, commands, name, args_.isolate_file, &options, seed, run_as)?;
+        },
+        Tool::Sweep { params, runner, program, args, n, interval, warmup } => {
+            let program = models::Program{
+                runner, program, args, env: HashMap::new(), working_dir: None, timeout: None, run_as: run_as.to_owned()
+            };
+            let options = models::BenchmarkOptions{ n, interval, warmup, target_cv: None, max_runs: n, max_time: None };
+            tools::sweep(&source, args_.delay, &program, name, params, args_.isolate_file, &options)?;
+        },
+        Tool::Suite { manifest } => {
+            tools::suite(&source, args_.delay, manifest, name, run_as)?;
         },
         Tool::BenchmarkInt { runner, program, background_log } => {
             if !background_log {
                 common::setup_ncurses();
             }
-            tools::benchmark_interactive(runner, program, args_.delay, system_start_time,
-                                         background_log, args_.run_time_limit, name, args_.isolate_file);
+            let program = models::Program{
+                runner, program, args: vec![], env: HashMap::new(), working_dir: None, timeout: None, run_as
+            };
+            tools::benchmark_interactive(&source, &program, args_.delay, system_start_time,
+                                         background_log, args_.run_time_limit, name, args_.isolate_file)?;
         },
         Tool::List { input } => {
-            tools::list(input);
-        },
-        Tool::PrettyPrint { file } => {
-            tools::pretty_print(file)
+            tools::list(&source, input)?;
         },
-        Tool::Isolate { measure, file } => {
-            match file {
+        Tool::Isolate { measure, migrate, .. } => {
+            match migrate {
                 Some(path) => {
-                    // generate data
-                    tools::generate_isolate_data(path);
+                    tools::migrate_isolate_data(&source, path)?;
                 },
-                _ => {
+                None => {
                     // measure data basis
-                    tools::measure_isolate_data(args_.delay, measure, system_start_time);
+                    tools::measure_isolate_data(&source, args_.delay, measure, system_start_time)?;
                 }
             }
-        }
+        },
+        Tool::Analyze { .. } | Tool::PrettyPrint { .. } | Tool::Fixture { .. } => {},
+        Tool::Doctor { } | Tool::SetupPermissions { .. } => {}
     }
-}
\ No newline at end of file
+
+    return finish_session(session);
+}
+
+fn finish_session(session: Option<&logger::LogSession>) -> error::Result<()> {
+    match session {
+        Some(session) => {
+            session.finish()?;
+            println!("Output written to {}", session.dir.display());
+        },
+        None => {}
+    }
+
+    return Ok(());
+}
+
+/// Whether `tool` reads energy counters - the others only read and write files
+fn needs_source(tool: &Tool) -> bool {
+    return match tool {
+        Tool::Analyze { .. } | Tool::PrettyPrint { .. } | Tool::Fixture { .. } => false,
+        Tool::Isolate { file, migrate, .. } => file.is_none() || migrate.is_some(),
+        Tool::Doctor { } | Tool::SetupPermissions { .. } => false,
+        _ => true
+    };
+}
+
+/// Name of the tool for its output directory - None for tools that don't write any files
+fn session_tool(tool: &Tool) -> Option<&'static str> {
+    return match tool {
+        Tool::Live { } => Some("live"),
+        Tool::Benchmark { .. } => Some("benchmark"),
+        Tool::Compare { .. } => Some("compare"),
+        Tool::Sweep { .. } => Some("sweep"),
+        Tool::Analyze { .. } => Some("analyze"),
+        Tool::Suite { .. } => Some("suite"),
+        Tool::BenchmarkInt { .. } => Some("benchmark-int"),
+        Tool::Isolate { .. } => Some("isolate"),
+        Tool::List { .. } | Tool::PrettyPrint { .. } | Tool::Fixture { .. } => None,
+        Tool::Doctor { } | Tool::SetupPermissions { .. } => None
+    };
+}
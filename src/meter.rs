@@ -0,0 +1,164 @@
+use crate::common;
+use crate::error::Result;
+use crate::models;
+use crate::region::Region;
+use crate::sources;
+use crate::sources::Source;
+
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Energy (J)
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Joules(pub f64);
+
+/// Power (W)
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Watts(pub f64);
+
+impl Joules {
+    pub fn watt_hours(&self) -> f64 {
+        return common::watt_hours(self.0);
+    }
+
+    pub fn kwatt_hours(&self) -> f64 {
+        return common::kwatt_hours(self.0);
+    }
+}
+
+/// Consumption of a single zone since the start of a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Measurement {
+    /// Zone id, e.g. `package-0/core`
+    pub zone: String,
+    pub elapsed: Duration,
+    pub energy: Joules,
+    /// Average power since the start of the session
+    pub average_power: Watts,
+    /// Average power since the previous sample
+    pub current_power: Watts
+}
+
+impl From<&models::RAPLData> for Measurement {
+    fn from(data: &models::RAPLData) -> Measurement {
+        return Measurement{
+            zone: data.zone.to_owned(),
+            elapsed: Duration::from_secs_f64(data.time_elapsed.max(0.)),
+            energy: Joules(data.power_j),
+            average_power: Watts(data.watts),
+            current_power: Watts(data.watts_since_last)
+        };
+    }
+}
+
+/// Measures the zones of an energy source.
+///
+/// ```no_run
+/// # fn main() -> Result<(), raplrs::Error> {
+/// let meter = raplrs::Meter::detect()?;
+/// let session = meter.start()?;
+/// // ... code to measure ...
+/// for m in session.stop()? {
+///     println!("{}: {:.3} J", m.zone, m.energy.0);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Meter {
+    source: Source,
+    // regions whose guards were dropped
+    regions: Mutex<Vec<Region>>
+}
+
+impl Meter {
+    pub fn new(source: Source) -> Meter {
+        return Meter{ source, regions: Mutex::new(vec![]) };
+    }
+
+    /// Meter reading from the first energy source available on this machine
+    pub fn detect() -> Result<Meter> {
+        return Ok(Meter::new(sources::create_source("auto", Path::new("/sys"), Path::new("/dev/cpu"))?));
+    }
+
+    pub fn source(&self) -> &Source {
+        return &self.source;
+    }
+
+    pub fn zones(&self) -> Result<Vec<models::RAPLZone>> {
+        return self.source.zones();
+    }
+
+    /// Regions recorded by dropped `EnergyGuard`s, in the order they ended
+    pub fn regions(&self) -> Vec<Region> {
+        return self.regions.lock().unwrap().to_vec();
+    }
+
+    /// Remove and return all recorded regions
+    pub fn take_regions(&self) -> Vec<Region> {
+        return self.regions.lock().unwrap().drain(..).collect();
+    }
+
+    pub(crate) fn record(&self, region: Region) {
+        self.regions.lock().unwrap().push(region);
+    }
+
+    /// Take the initial readings of all zones
+    pub fn start(&self) -> Result<Session> {
+        let now = Instant::now();
+
+        return Ok(Session{
+            source: self.source.clone(),
+            zones: common::setup_rapl_data(self.source.as_ref())?,
+            start_time: now,
+            prev_time: now
+        });
+    }
+}
+
+/// A running measurement, started by `Meter::start`.
+///
+/// Counters wrap around after a few minutes to hours depending on load - only a single wrap-around
+/// between two samples can be accounted for, so long sessions should be sampled periodically.
+pub struct Session {
+    source: Source,
+    zones: Vec<models::RAPLData>,
+    start_time: Instant,
+    prev_time: Instant
+}
+
+impl Session {
+    /// Read all zones and return their consumption since the session started
+    pub fn sample(&mut self) -> Result<Vec<Measurement>> {
+        let now = Instant::now();
+        let temperatures = common::read_temperatures();
+        let mut zones = vec![];
+
+        for zone in &self.zones {
+            let mut data = common::calculate_power_metrics(
+                self.source.as_ref(), zone.to_owned(), now, self.start_time, self.prev_time)?;
+            temperatures.apply(&mut data);
+            zones.push(data);
+        }
+
+        self.zones = zones;
+        self.prev_time = now;
+        return Ok(self.snapshot());
+    }
+
+    /// Consumption as of the last sample, without reading the zones
+    pub fn snapshot(&self) -> Vec<Measurement> {
+        return self.zones.iter().map(Measurement::from).collect();
+    }
+
+    /// Raw data as of the last sample, e.g. for `common::print_result_line`
+    pub fn data(&self) -> &Vec<models::RAPLData> {
+        return &self.zones;
+    }
+
+    /// Take a final sample and end the session
+    pub fn stop(mut self) -> Result<Vec<Measurement>> {
+        return self.sample();
+    }
+}
@@ -1,16 +1,37 @@
+use crate::stats;
+use crate::regression;
+
 use serde;
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 
-#[derive(Debug)]
-pub(crate) struct RAPLZone {
+#[derive(Debug, Clone)]
+pub struct RAPLZone {
     pub path: String,
-    pub name: String
+    pub name: String,
+    /// Unique, stable identifier, e.g. `package-1/core` - names alone collide on multi-socket machines
+    pub id: String,
+    /// Path of the zone this is a subzone of
+    pub parent: Option<String>,
+    pub enabled: bool,
+    /// Names of the power limit constraints of the zone, e.g. `long_term`
+    pub constraints: Vec<String>
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct RAPLData {
+pub struct RAPLData {
     #[serde(skip_serializing, skip_deserializing)]
     pub path: String,
+    /// Identifies the run across machines - empty in .csv files from before schema 2, as are `sample` and `timestamp`
+    #[serde(default)]
+    pub run_id: String,
+    /// Index of the poll, shared by the rows of the zones polled together
+    #[serde(default)]
+    pub sample: u64,
+    /// Unix time of the poll (s)
+    #[serde(default)]
+    pub timestamp: f64,
     pub zone: String,
     pub time_elapsed: f64,
     pub power_j: f64,
@@ -19,11 +40,19 @@ pub(crate) struct RAPLData {
     pub start_power: f64,
     pub prev_power: f64,
     pub prev_power_reading: f64,
-    pub temp: f64,
+    /// Temperature of the package the zone belongs to (°C) - of the hottest package for zones outside of one
+    #[serde(default)]
+    pub package_temp: Option<f64>,
+    /// Temperature of the hottest core of the package (°C)
+    #[serde(default)]
+    pub core_temp_max: Option<f64>,
+    /// Average temperature of the cores of the package (°C) - the `temp` column of schema 2
+    #[serde(default, alias = "temp")]
+    pub core_temp_avg: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct IsolateData {
+pub struct IsolateData {
     pub power_j: StatData,
     pub watts: StatData,
     pub watts_since_last: StatData,
@@ -32,9 +61,251 @@ pub(crate) struct IsolateData {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct StatData {
+pub struct StatData {
     pub min: f64,
     pub max: f64,
     pub avg: f64,
     pub total: f64
-}
\ No newline at end of file
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureZone {
+    pub name: String,
+    /// Control type of a package, defaults to `intel-rapl`
+    #[serde(default)]
+    pub control_type: Option<String>,
+    pub max_energy_range_uj: u64,
+    pub energy_uj: Vec<u64>,
+    #[serde(default)]
+    pub subzones: Vec<FixtureZone>
+}
+
+/// A program to benchmark and how to start it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Program {
+    pub runner: Option<PathBuf>,
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Extra environment variables for the program
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<PathBuf>,
+    /// Kill the program after this many seconds
+    pub timeout: Option<u64>,
+    /// Run the program as another user, with dropped privileges
+    #[serde(default)]
+    pub run_as: Option<RunAs>
+}
+
+/// An unprivileged user to run benchmarked programs as, see `permissions::run_as`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunAs {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    /// Supplementary groups of the user
+    pub groups: Vec<u32>,
+    pub home: PathBuf
+}
+
+/// How many times, and for how long, to run a benchmark
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkOptions {
+    /// Amount of iterations - the minimum amount when `target_cv` is set
+    pub n: u64,
+    /// Seconds between iterations
+    pub interval: u64,
+    /// Unmeasured runs before the measured iterations
+    pub warmup: u64,
+    /// Keep iterating until the coefficient of variation of the package energy is at most this
+    pub target_cv: Option<f64>,
+    /// Upper bound of iterations when `target_cv` is set
+    pub max_runs: u64,
+    /// Stop iterating after this many seconds
+    pub max_time: Option<u64>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The fixed amount of iterations was run
+    Iterations,
+    /// The coefficient of variation reached the target
+    Converged,
+    MaxRuns,
+    MaxTime
+}
+
+/// Statistics of a zone across benchmark iterations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneSummary {
+    pub zone: String,
+    pub power_j: stats::Summary,
+    pub time_elapsed: stats::Summary,
+    pub watts: stats::Summary
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkSummary {
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Unmeasured runs before the recorded iterations
+    pub warmup: u64,
+    pub runs: usize,
+    pub stop_reason: StopReason,
+    /// Coefficient of variation of the package energy, if tracked
+    pub cv: Option<f64>,
+    /// Per-iteration .csv files
+    pub files: Vec<String>,
+    pub zones: Vec<ZoneSummary>
+}
+
+/// Difference of a zone between the baseline program and another program
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneComparison {
+    pub zone: String,
+    pub baseline: String,
+    pub program: String,
+    /// Mean energy of the baseline (J)
+    pub baseline_power_j: f64,
+    /// Mean energy of the program (J)
+    pub power_j: f64,
+    /// Difference of the means, program minus baseline (J)
+    pub diff_power_j: f64,
+    /// Difference relative to the baseline (%), None if the baseline consumed no energy
+    pub diff_percent: Option<f64>,
+    pub welch_p: f64,
+    pub mann_whitney_p: f64,
+    pub cohens_d: f64,
+    pub cliffs_delta: f64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonSummary {
+    pub name: String,
+    /// Seed of the randomized run order
+    pub seed: u64,
+    pub programs: Vec<BenchmarkSummary>,
+    pub comparisons: Vec<ZoneComparison>
+}
+
+/// Benchmarks to run by `suite`, read from a TOML manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuiteManifest {
+    pub name: Option<String>,
+    #[serde(rename = "benchmark")]
+    pub benchmarks: Vec<SuiteBenchmark>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuiteBenchmark {
+    pub name: String,
+    /// Relative paths are resolved against `working_dir`, which defaults to the directory of the manifest
+    pub program: PathBuf,
+    pub runner: Option<PathBuf>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Run as `benchmark-int` with background logging rather than as `benchmark`, the run options don't apply then
+    #[serde(default)]
+    pub interactive: bool,
+    /// Defaults to 1
+    pub n: Option<u64>,
+    pub interval: Option<u64>,
+    pub warmup: Option<u64>,
+    pub target_cv: Option<f64>,
+    /// Defaults to 100
+    pub max_runs: Option<u64>,
+    pub max_time: Option<u64>,
+    pub isolate_file: Option<PathBuf>,
+    /// Kill the program after this many seconds
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<PathBuf>
+}
+
+impl SuiteBenchmark {
+    /// Names of the run options set for this benchmark
+    pub fn run_options(&self) -> Vec<&'static str> {
+        let set = [
+            ("n", self.n.is_some()),
+            ("interval", self.interval.is_some()),
+            ("warmup", self.warmup.is_some()),
+            ("target_cv", self.target_cv.is_some()),
+            ("max_runs", self.max_runs.is_some()),
+            ("max_time", self.max_time.is_some())
+        ];
+
+        return set.iter().filter(|(_, is_set)| *is_set).map(|(option, _)| *option).collect();
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuiteSummary {
+    pub name: String,
+    pub manifest: String,
+    pub benchmarks: Vec<BenchmarkSummary>
+}
+
+/// A parameter of `sweep` and the values it takes, e.g. `n` = 10, 20, 30
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepAxis {
+    pub name: String,
+    pub values: Vec<String>
+}
+
+/// Benchmark results for one combination of sweep parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepPoint {
+    /// Parameter names and values, in the order of the axes
+    pub params: Vec<(String, String)>,
+    pub summary: BenchmarkSummary
+}
+
+/// Fits of energy versus a sweep parameter, for one zone and one combination of the other parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScalingAnalysis {
+    pub zone: String,
+    /// Values of the other parameters
+    pub params: Vec<(String, String)>,
+    /// Parameter the energy is fitted against
+    pub x: String,
+    pub points: usize,
+    pub fits: Vec<ScalingFit>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScalingFit {
+    #[serde(flatten)]
+    pub fit: regression::Fit,
+    /// Predicted energy (J) per requested parameter value
+    pub predictions: Vec<(f64, f64)>
+}
+
+/// One invocation of a tool, written as `manifest.json` to its output directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionManifest {
+    pub tool: String,
+    pub name: String,
+    /// Command line raplrs was started with
+    pub command: Vec<String>,
+    /// Energy source measured with, None for tools that only read files
+    pub source: Option<String>,
+    pub isolate_file: Option<String>,
+    /// User the benchmarked programs ran as, None if they ran as the user of raplrs
+    #[serde(default)]
+    pub run_as: Option<String>,
+    /// Unix time (s)
+    pub start_time: f64,
+    pub end_time: Option<f64>,
+    pub host: HostInfo,
+    /// Files written so far, relative to the output directory - one per iteration for benchmarks
+    pub files: Vec<String>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub hostname: String,
+    pub kernel: String,
+    pub cpu_model: String,
+    pub cpus: usize
+}
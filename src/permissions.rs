@@ -0,0 +1,439 @@
+use crate::error::{Error, Result};
+use crate::models;
+use crate::sources;
+use crate::sources::EnergySource;
+use crate::thermal;
+
+use regex::Regex;
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Whether unprivileged processes may open system-wide perf events, see perf_event_open(2)
+pub const PERF_PARANOID_PATH: &str = "/proc/sys/kernel/perf_event_paranoid";
+/// Reapplies the group read access whenever a zone appears, e.g. on boot
+pub const UDEV_RULE_PATH: &str = "/etc/udev/rules.d/99-raplrs.rules";
+/// Group granted read access to the energy counters by default
+pub const DEFAULT_GROUP: &str = "raplrs";
+/// Holds the PATH of login shells, as `ENV_PATH PATH=...`
+const LOGIN_DEFS_PATH: &str = "/etc/login.defs";
+/// PATH of programs run as another user if login.defs doesn't set one - sudo's secure_path lacks e.g. /usr/games
+const DEFAULT_USER_PATH: &str = "/usr/local/bin:/usr/bin:/bin:/usr/local/games:/usr/games";
+/// Variables passed on to programs run as another user, besides the `LC_*` ones - the rest of the environment is root's
+const KEPT_ENV_VARS: [&str; 8] = ["TERM", "COLORTERM", "LANG", "LANGUAGE", "TZ", "DISPLAY", "WAYLAND_DISPLAY", "XAUTHORITY"];
+
+const SETUP_HINT: &str = "sudo raplrs setup-permissions";
+/// Why `energy_uj` is root-only since Linux 5.10, shown whenever the counters are opened up to a group
+pub const PLATYPUS_WARNING: &str = "the energy counters leak information about the data processed by other processes, \
+                                    e.g. cryptographic keys (PLATYPUS, CVE-2020-8694) - Linux 5.10 made them readable by root \
+                                    only for that reason";
+// group read bit
+const MODE_GROUP_READ: u32 = 0o040;
+// owner rwx, group rx - the mode of a binary only its group may run
+const MODE_GROUP_EXECUTABLE: u32 = 0o750;
+/// Lets a process open system-wide perf events regardless of perf_event_paranoid, since Linux 5.8
+const PERF_CAPABILITY: &str = "cap_perfmon";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+    Ok,
+    /// The feature isn't available on this machine, e.g. the driver isn't loaded
+    Warning,
+    /// The feature is available, but not to the current user
+    Failed
+}
+
+/// Outcome of one preflight check
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// Energy source checked, e.g. `powercap`, or `temperature`
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// How to fix a warning or failure
+    pub fix: Option<String>
+}
+
+impl Check {
+    fn new(name: &str, status: CheckStatus, detail: String, fix: Option<&str>) -> Check {
+        return Check{ name: name.to_string(), status, detail, fix: fix.map(|f| f.to_string()) };
+    }
+}
+
+/// Check every energy source and the temperature sensors for what the current user can read
+pub fn preflight(sysfs_root: &Path, msr_dir: &Path) -> Vec<Check> {
+    return vec![
+        check_zones("powercap", &sources::PowercapSource::new(sysfs_root), "is the intel_rapl_common module loaded?"),
+        check_zones("hwmon", &sources::HwmonSource::new(sysfs_root), "only AMD hosts with amd_energy, zenergy or zenpower have any"),
+        check_perf(sysfs_root),
+        check_msr(sysfs_root, msr_dir),
+        check_temperature(sysfs_root)
+    ];
+}
+
+/// Read the counter and range of every zone of `source`
+fn check_zones(name: &str, source: &dyn EnergySource, missing: &str) -> Check {
+    let zones = match source.zones() {
+        Ok(z) => z,
+        Err(e) => return Check::new(name, CheckStatus::Failed, e.to_string(), None)
+    };
+    if zones.is_empty() {
+        return Check::new(name, CheckStatus::Warning, format!("no zones found - {}", missing), None);
+    }
+
+    let errors: Vec<Error> = zones.iter()
+        .filter_map(|z| source.read_energy(&z.path).and_then(|_| source.read_energy_range(&z.path)).err())
+        .collect();
+    return match errors.first() {
+        None => Check::new(name, CheckStatus::Ok, format!("{} zones readable", zones.len()), None),
+        Some(e) => {
+            let fix = match e {
+                Error::PermissionDenied{ .. } => Some(SETUP_HINT),
+                _ => None
+            };
+            let detail = format!("{} of {} zones unreadable, e.g. {}", errors.len(), zones.len(), e);
+            Check::new(name, CheckStatus::Failed, detail, fix)
+        }
+    };
+}
+
+fn check_perf(sysfs_root: &Path) -> Check {
+    let paranoid = match fs::read_to_string(PERF_PARANOID_PATH) {
+        Ok(p) => format!("perf_event_paranoid is {}", p.trim()),
+        Err(_) => format!("{} is unreadable", PERF_PARANOID_PATH)
+    };
+
+    return match sources::PerfSource::new(sysfs_root) {
+        Ok(source) => match source.zones() {
+            Ok(zones) if zones.is_empty() => Check::new("perf", CheckStatus::Warning, "the power PMU has no energy events".to_string(), None),
+            Ok(zones) => Check::new("perf", CheckStatus::Ok, format!("{} events readable, {}", zones.len(), paranoid), None),
+            Err(e) => Check::new("perf", CheckStatus::Failed, e.to_string(), None)
+        },
+        Err(e @ Error::SourceUnavailable{ .. }) => Check::new("perf", CheckStatus::Warning, e.to_string(), None),
+        Err(e) => Check::new("perf", CheckStatus::Failed, format!("{}, {}", e, paranoid), Some("sudo raplrs setup-permissions --perf"))
+    };
+}
+
+fn check_msr(sysfs_root: &Path, msr_dir: &Path) -> Check {
+    if !msr_dir.join("0/msr").exists() {
+        let detail = format!("there is no {}/0/msr - the msr module isn't loaded", msr_dir.display());
+        return Check::new("msr", CheckStatus::Warning, detail, Some("sudo modprobe msr"));
+    }
+
+    // reading MSRs requires CAP_SYS_RAWIO, which a group can't grant
+    return match sources::MsrSource::new(msr_dir, sysfs_root).zones() {
+        Ok(zones) => Check::new("msr", CheckStatus::Ok, format!("{} registers readable", zones.len()), None),
+        Err(e @ Error::PermissionDenied{ .. }) => Check::new("msr", CheckStatus::Failed, e.to_string(), None),
+        Err(e) => Check::new("msr", CheckStatus::Warning, e.to_string(), None)
+    };
+}
+
+fn check_temperature(sysfs_root: &Path) -> Check {
+    let sensors = thermal::Thermal::discover(sysfs_root).sensors;
+    if sensors.is_empty() {
+        let detail = "no coretemp, k10temp or zenpower hwmon device, nor a CPU thermal zone - temperatures are left empty";
+        return Check::new("temperature", CheckStatus::Warning, detail.to_string(),
+                          Some("load the sensor driver of the CPU, e.g. `sudo modprobe coretemp` or `sudo modprobe k10temp`"));
+    }
+
+    let unreadable: Vec<&thermal::TempSensor> = sensors.iter().filter(|s| s.read().is_none()).collect();
+    return match unreadable.first() {
+        None => {
+            let mut packages: Vec<u32> = sensors.iter().map(|s| s.package).collect();
+            packages.sort_unstable();
+            packages.dedup();
+            let detail = format!("{} sensors readable on {} packages", sensors.len(), packages.len());
+            Check::new("temperature", CheckStatus::Ok, detail, None)
+        },
+        Some(s) => {
+            let detail = format!("{} of {} sensors unreadable, e.g. {}", unreadable.len(), sensors.len(), s.path.display());
+            Check::new("temperature", CheckStatus::Warning, detail, None)
+        }
+    };
+}
+
+/// A change to the system made by `setup-permissions`
+#[derive(Debug, Clone)]
+pub enum Action {
+    CreateGroup{ group: String },
+    AddToGroup{ user: String, group: String },
+    /// Make `path` owned and readable by `group`
+    GrantRead{ path: PathBuf, group: String },
+    WriteFile{ path: PathBuf, contents: String },
+    /// Make the executable `path` owned by root and runnable only by root and `group`
+    RestrictExecutable{ path: PathBuf, group: String },
+    /// Set the file capability `capability` on the executable `path`, which only `group` may run
+    GrantCapability{ path: PathBuf, capability: String, group: String }
+}
+
+impl Action {
+    pub fn describe(&self) -> String {
+        return match self {
+            Action::CreateGroup{ group } => format!("Create the system group {}", group),
+            Action::AddToGroup{ user, group } => format!("Add {} to {} - log in again for it to take effect", user, group),
+            Action::GrantRead{ path, group } => format!("Grant {} read access to {}", group, path.display()),
+            Action::WriteFile{ path, contents } => {
+                let indented: Vec<String> = contents.lines().map(|l| format!("    {}", l)).collect();
+                format!("Write {}:\n{}", path.display(), indented.join("\n"))
+            },
+            Action::RestrictExecutable{ path, group } => {
+                format!("Restrict running {} to root and {} (owner root, mode 0{:o})", path.display(), group, MODE_GROUP_EXECUTABLE)
+            },
+            Action::GrantCapability{ path, capability, group } => format!(
+                "Grant {} to {}\n    Warning: members of {} running it can read every system-wide perf event, \
+                 including those of other users' processes - only add users trusted with that",
+                capability, path.display(), group
+            )
+        };
+    }
+
+    pub fn apply(&self) -> Result<()> {
+        return match self {
+            Action::CreateGroup{ group } => {
+                run_command(Command::new("groupadd").arg("--system").arg(group), format!("create the group {}", group))
+            },
+            Action::AddToGroup{ user, group } => {
+                run_command(Command::new("usermod").arg("-aG").arg(group).arg(user), format!("add {} to {}", user, group))
+            },
+            Action::GrantRead{ path, group } => {
+                let action = format!("grant {} read access to {}", group, path.display());
+                let gid = group_id(group).ok_or_else(|| Error::Usage(format!("There is no group {}", group)))?;
+                let mode = fs::metadata(path).map_err(|e| Error::Setup{ action: action.to_owned(), error: e })?.permissions().mode();
+
+                std::os::unix::fs::chown(path, None, Some(gid))
+                    .and_then(|_| fs::set_permissions(path, fs::Permissions::from_mode(mode | MODE_GROUP_READ)))
+                    .map_err(|e| Error::Setup{ action, error: e })
+            },
+            Action::WriteFile{ path, contents } => {
+                let action = format!("write {}", path.display());
+                let dir = path.parent().unwrap_or(Path::new("/"));
+
+                fs::create_dir_all(dir)
+                    .and_then(|_| fs::write(path, contents))
+                    .map_err(|e| Error::Setup{ action, error: e })
+            },
+            Action::RestrictExecutable{ path, group } => {
+                let action = format!("restrict running {} to {}", path.display(), group);
+                let gid = group_id(group).ok_or_else(|| Error::Usage(format!("There is no group {}", group)))?;
+
+                std::os::unix::fs::chown(path, Some(0), Some(gid))
+                    .and_then(|_| fs::set_permissions(path, fs::Permissions::from_mode(MODE_GROUP_EXECUTABLE)))
+                    .map_err(|e| Error::Setup{ action, error: e })
+            },
+            Action::GrantCapability{ path, capability, .. } => {
+                run_command(Command::new("setcap").arg(format!("{}=ep", capability)).arg(path),
+                            format!("grant {} to {}", capability, path.display()))
+            }
+        };
+    }
+}
+
+/// The actions granting `group` read access to the energy counters under `sysfs_root` - now, and through a udev rule on
+/// every boot. `user` is added to the group, and the `perf` binary (i.e. raplrs) may open system-wide perf events
+/// through CAP_PERFMON, but only be run by the group.
+pub fn setup_actions(sysfs_root: &Path, group: &str, user: Option<String>, perf: Option<PathBuf>) -> Result<Vec<Action>> {
+    // the name ends up in the shell commands of the udev rule
+    if !is_group_name(group) {
+        return Err(Error::Usage(format!("{:?} is not a valid group name, expected [a-z_][a-z0-9_-]* of at most 32 characters", group)));
+    }
+    let mut actions = vec![];
+
+    if group_id(group).is_none() {
+        actions.push(Action::CreateGroup{ group: group.to_string() });
+    }
+    match user {
+        Some(user) => actions.push(Action::AddToGroup{ user, group: group.to_string() }),
+        None => {}
+    }
+
+    actions.push(Action::WriteFile{ path: PathBuf::from(UDEV_RULE_PATH), contents: udev_rule(group) });
+    for zone in sources::PowercapSource::new(sysfs_root).zones()? {
+        actions.push(Action::GrantRead{ path: Path::new(&zone.path).join("energy_uj"), group: group.to_string() });
+    }
+    for zone in sources::HwmonSource::new(sysfs_root).zones()? {
+        actions.push(Action::GrantRead{ path: PathBuf::from(&zone.path), group: group.to_string() });
+    }
+
+    // changing the owner clears file capabilities, so the binary is restricted first
+    match perf {
+        Some(path) => {
+            actions.push(Action::RestrictExecutable{ path: path.to_owned(), group: group.to_string() });
+            actions.push(Action::GrantCapability{ path, capability: PERF_CAPABILITY.to_string(), group: group.to_string() });
+        },
+        None => {}
+    }
+
+    return Ok(actions);
+}
+
+/// Whether `group` is a portable group name, which is safe to put in a udev rule
+pub fn is_group_name(group: &str) -> bool {
+    let pattern = Regex::new(r"^[a-z_][a-z0-9_-]*$").unwrap();
+    return group.len() <= 32 && pattern.is_match(group);
+}
+
+/// udev rule making the powercap and hwmon energy counters readable by `group` whenever they appear
+pub fn udev_rule(group: &str) -> String {
+    let mut rule = format!(
+        "# written by `raplrs setup-permissions` - lets members of {group} read the RAPL energy counters\n\
+         # warning: {warning}\n\
+         ACTION==\"add\", SUBSYSTEM==\"powercap\", RUN+=\"/bin/sh -c 'chgrp {group} /sys%p/energy_uj && chmod g+r /sys%p/energy_uj'\"\n",
+        group = group,
+        warning = PLATYPUS_WARNING
+    );
+    for driver in sources::ENERGY_HWMON_DRIVERS {
+        rule.push_str(&format!(
+            "ACTION==\"add\", SUBSYSTEM==\"hwmon\", ATTR{{name}}==\"{driver}\", \
+             RUN+=\"/bin/sh -c 'chgrp {group} /sys%p/energy*_input && chmod g+r /sys%p/energy*_input'\"\n",
+            driver = driver,
+            group = group
+        ));
+    }
+
+    return rule;
+}
+
+/// Whether `var` is passed on to programs run as another user
+pub fn is_kept_var(var: &str) -> bool {
+    return var.starts_with("LC_") || KEPT_ENV_VARS.contains(&var);
+}
+
+/// The user to run benchmarked programs as - `user` (a name or uid) or, when raplrs runs through sudo, the user invoking it.
+///
+/// `group` defaults to `SUDO_GID` for the user invoking sudo, and to the primary group of `user` otherwise.
+/// None if raplrs runs as a normal user without `user`, as the programs then run unprivileged already
+pub fn run_as(user: Option<String>, group: Option<String>) -> Result<Option<models::RunAs>> {
+    let (user, group) = match user {
+        Some(user) => (user, group),
+        None => match env::var("SUDO_UID") {
+            Ok(uid) if is_root() => (uid, group.or_else(|| env::var("SUDO_GID").ok())),
+            _ => {
+                if group.is_some() {
+                    return Err(Error::Usage("--group requires --user, or running raplrs through sudo".to_string()));
+                }
+                return Ok(None);
+            }
+        }
+    };
+    if !is_root() {
+        return Err(Error::Usage("--user requires running raplrs as root, e.g. through sudo".to_string()));
+    }
+
+    let (name, uid, user_gid, home) = user_entry(&user).ok_or_else(|| Error::Usage(format!("There is no user {}", user)))?;
+    let gid = match group {
+        Some(group) => group_id(&group).or_else(|| group.parse::<u32>().ok())
+            .ok_or_else(|| Error::Usage(format!("There is no group {}", group)))?,
+        None => user_gid
+    };
+    let mut groups = user_groups(&name, user_gid);
+    if !groups.contains(&gid) {
+        groups.push(gid);
+    }
+
+    return Ok(Some(models::RunAs{ name, uid, gid, groups, home }));
+}
+
+/// Spawn `command` as `user`, with HOME, PATH, USER and the XDG variables of that user rather than of root.
+/// Only the terminal, locale and display variables are kept from the environment of raplrs
+pub fn drop_privileges(command: &mut Command, user: &models::RunAs) {
+    let kept: Vec<(String, String)> = env::vars().filter(|(var, _)| is_kept_var(var)).collect();
+    command.env_clear()
+        .envs(kept)
+        .env("HOME", &user.home)
+        .env("USER", &user.name)
+        .env("LOGNAME", &user.name)
+        .env("PATH", login_path());
+    let runtime_dir = PathBuf::from(format!("/run/user/{}", user.uid));
+    if runtime_dir.exists() {
+        command.env("XDG_RUNTIME_DIR", runtime_dir);
+    }
+
+    let (uid, gid, groups) = (user.uid, user.gid, user.groups.to_owned());
+    // the groups must be set while still root, i.e. before the uid - and only async-signal-safe calls may be made here
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setgroups(groups.len(), groups.as_ptr()) != 0 || libc::setgid(gid) != 0 || libc::setuid(uid) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// PATH of login shells from login.defs
+fn login_path() -> String {
+    let login_defs = fs::read_to_string(LOGIN_DEFS_PATH).unwrap_or_default();
+    let path = login_defs.lines()
+        .find_map(|l| l.trim().strip_prefix("ENV_PATH")?.trim().strip_prefix("PATH=").map(|p| p.to_string()));
+
+    return path.unwrap_or_else(|| DEFAULT_USER_PATH.to_string());
+}
+
+/// Name, uid, primary gid and home directory of the user named `user`, or with uid `user`
+fn user_entry(user: &str) -> Option<(String, u32, u32, PathBuf)> {
+    let name = CString::new(user).ok()?;
+    let mut entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if entry.is_null() {
+        let uid = user.parse::<u32>().ok()?;
+        entry = unsafe { libc::getpwuid(uid) };
+    }
+    if entry.is_null() {
+        return None;
+    }
+
+    let entry = unsafe { &*entry };
+    let name = unsafe { CStr::from_ptr(entry.pw_name) }.to_string_lossy().to_string();
+    let home = unsafe { CStr::from_ptr(entry.pw_dir) }.to_string_lossy().to_string();
+
+    return Some((name, entry.pw_uid, entry.pw_gid, PathBuf::from(home)));
+}
+
+/// Every group `user` is a member of, including its primary group `gid`
+fn user_groups(user: &str, gid: u32) -> Vec<u32> {
+    let name = match CString::new(user) {
+        Ok(n) => n,
+        Err(_) => return vec![gid]
+    };
+    let mut count: libc::c_int = 32;
+
+    loop {
+        let mut groups: Vec<libc::gid_t> = vec![0; count as usize];
+        // fails, setting count to the amount of groups, if there are more than fit
+        let res = unsafe { libc::getgrouplist(name.as_ptr(), gid, groups.as_mut_ptr(), &mut count) };
+        if res >= 0 {
+            groups.truncate(count as usize);
+            return groups;
+        }
+        count = count.max(2 * groups.len() as libc::c_int);
+    }
+}
+
+pub fn is_root() -> bool {
+    return unsafe { libc::geteuid() } == 0;
+}
+
+/// Id of the group named `group`, None if there is no such group
+pub fn group_id(group: &str) -> Option<u32> {
+    let name = CString::new(group).ok()?;
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if entry.is_null() {
+        return None;
+    }
+
+    return Some(unsafe { (*entry).gr_gid });
+}
+
+fn run_command(command: &mut Command, action: String) -> Result<()> {
+    let status = command.status().map_err(|e| Error::Setup{ action: action.to_owned(), error: e })?;
+    if !status.success() {
+        let error = io::Error::other(format!("{:?} exited with {}", command, status));
+        return Err(Error::Setup{ action, error });
+    }
+
+    return Ok(());
+}
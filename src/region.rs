@@ -0,0 +1,201 @@
+use crate::error::Result;
+use crate::meter::{Joules, Measurement, Meter, Session};
+
+use serde::{Serialize, Deserialize};
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    // names of the regions currently open on this thread, outermost first
+    static OPEN_REGIONS: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+}
+
+static DEFAULT_METER: OnceLock<Meter> = OnceLock::new();
+
+/// Energy consumed while a region of code ran
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Region {
+    /// Name of the region, prefixed by the regions it is nested in, e.g. `load/parse`
+    pub name: String,
+    /// Amount of regions this region is nested in
+    pub depth: usize,
+    pub duration: Duration,
+    /// Consumption of every zone during the region
+    pub zones: Vec<Measurement>
+}
+
+impl Region {
+    /// Energy consumed by the zone with id `zone`
+    pub fn energy(&self, zone: &str) -> Option<Joules> {
+        return self.zones.iter().find(|m| m.zone == zone).map(|m| m.energy);
+    }
+}
+
+/// Measures a region of code until it is finished or dropped.
+///
+/// Regions opened while another is open on the same thread are nested in it.
+/// Dropped guards are recorded by their meter, see `Meter::regions` - unless the zones couldn't be read.
+/// `Meter::region` and `finish` panic if the zones can't be read, `Meter::try_region` and `try_finish` return the error.
+///
+/// Guards can't be sent to another thread, as nesting is tracked per thread:
+///
+/// ```compile_fail
+/// let guard = raplrs::region("main");
+/// std::thread::spawn(move || drop(guard));
+/// ```
+pub struct EnergyGuard<'a> {
+    meter: &'a Meter,
+    name: String,
+    depth: usize,
+    start_time: Instant,
+    session: Option<Session>,
+    // !Send, dropping on another thread would truncate that thread's open regions
+    _not_send: PhantomData<*const ()>
+}
+
+impl<'a> EnergyGuard<'a> {
+    fn start(meter: &'a Meter, name: &str) -> Result<EnergyGuard<'a>> {
+        let session = meter.start()?;
+        let (name, depth) = OPEN_REGIONS.with(|open| {
+            let mut open = open.borrow_mut();
+            let depth = open.len();
+            let name = match open.last() {
+                Some(parent) => format!("{}/{}", parent, name),
+                None => name.to_string()
+            };
+            open.push(name.to_owned());
+            (name, depth)
+        });
+
+        return Ok(EnergyGuard{
+            meter,
+            name,
+            depth,
+            start_time: Instant::now(),
+            session: Some(session),
+            _not_send: PhantomData
+        });
+    }
+
+    /// End the region and return its consumption, panics if the zones can't be read
+    pub fn finish(self) -> Region {
+        return self.try_finish().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// End the region and return its consumption
+    pub fn try_finish(mut self) -> Result<Region> {
+        return self.end();
+    }
+
+    fn end(&mut self) -> Result<Region> {
+        let zones = match self.session.take() {
+            Some(session) => session.stop(),
+            None => Ok(vec![])
+        };
+        let duration = self.start_time.elapsed();
+
+        // regions finished out of order close every region nested in them as well
+        OPEN_REGIONS.with(|open| open.borrow_mut().truncate(self.depth));
+
+        return Ok(Region{
+            name: self.name.to_owned(),
+            depth: self.depth,
+            duration,
+            zones: zones?
+        });
+    }
+}
+
+impl<'a> Drop for EnergyGuard<'a> {
+    fn drop(&mut self) {
+        if self.session.is_some() {
+            match self.end() {
+                Ok(region) => self.meter.record(region),
+                // there is no one to report to, and panicking in drop may abort
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+impl Meter {
+    /// Start measuring a region named `name`, panics if the zones can't be read
+    pub fn region(&self, name: &str) -> EnergyGuard<'_> {
+        return self.try_region(name).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Start measuring a region named `name`
+    pub fn try_region(&self, name: &str) -> Result<EnergyGuard<'_>> {
+        return EnergyGuard::start(self, name);
+    }
+
+    /// Measure the consumption of `f`, panics if the zones can't be read
+    pub fn measure<R, F: FnOnce() -> R>(&self, name: &str, f: F) -> (R, Region) {
+        return self.try_measure(name, f).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Measure the consumption of `f`
+    pub fn try_measure<R, F: FnOnce() -> R>(&self, name: &str, f: F) -> Result<(R, Region)> {
+        let guard = self.try_region(name)?;
+        let res = f();
+
+        return Ok((res, guard.try_finish()?));
+    }
+}
+
+/// Meter on the first energy source available on this machine, shared by `measure` and `region`.
+/// Panics if there is none
+pub fn default_meter() -> &'static Meter {
+    return try_default_meter().unwrap_or_else(|e| panic!("{}", e));
+}
+
+/// Meter on the first energy source available on this machine, shared by `try_measure` and `try_region`
+pub fn try_default_meter() -> Result<&'static Meter> {
+    match DEFAULT_METER.get() {
+        Some(meter) => return Ok(meter),
+        None => {}
+    }
+
+    // another thread may have set it in the meantime, either meter will do
+    let meter = Meter::detect()?;
+    return Ok(DEFAULT_METER.get_or_init(|| meter));
+}
+
+/// Measure the consumption of `f` with the default meter, panics if there is none or its zones can't be read.
+///
+/// RAPL counters update roughly every millisecond, so regions should run considerably longer than that.
+///
+/// ```no_run
+/// let (sum, region) = raplrs::measure(|| (0..1_000_000u64).sum::<u64>());
+/// for zone in region.zones {
+///     println!("{}: {:.3} J, {:.3} W", zone.zone, zone.energy.0, zone.average_power.0);
+/// }
+/// ```
+pub fn measure<R, F: FnOnce() -> R>(f: F) -> (R, Region) {
+    return default_meter().measure("measure", f);
+}
+
+/// Measure the consumption of `f` with the default meter
+///
+/// ```no_run
+/// # fn main() -> raplrs::error::Result<()> {
+/// let (sum, region) = raplrs::try_measure(|| (0..1_000_000u64).sum::<u64>())?;
+/// println!("{} took {:?}", sum, region.duration);
+/// # Ok(())
+/// # }
+/// ```
+pub fn try_measure<R, F: FnOnce() -> R>(f: F) -> Result<(R, Region)> {
+    return try_default_meter()?.try_measure("measure", f);
+}
+
+/// Start measuring a region named `name` with the default meter, panics if there is none or its zones can't be read
+pub fn region(name: &str) -> EnergyGuard<'static> {
+    return default_meter().region(name);
+}
+
+/// Start measuring a region named `name` with the default meter
+pub fn try_region(name: &str) -> Result<EnergyGuard<'static>> {
+    return try_default_meter()?.try_region(name);
+}
@@ -0,0 +1,173 @@
+use crate::stats;
+
+use serde::{Serialize, Deserialize};
+
+/// Shape of a fitted model of energy versus input size
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Model {
+    /// `y = a + b*x`
+    Linear,
+    /// `y = c0 + c1*x + c2*x^2 + ...`
+    Polynomial,
+    /// `y = a * x^b`
+    PowerLaw
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fit {
+    pub model: Model,
+    /// Lowest order first - `[a, b]` for linear and power-law models
+    pub coefficients: Vec<f64>,
+    /// Coefficient of determination on the original scale
+    pub r_squared: f64
+}
+
+impl Fit {
+    pub fn predict(&self, x: f64) -> f64 {
+        return match self.model {
+            Model::Linear | Model::Polynomial => {
+                self.coefficients.iter().enumerate().map(|(i, c)| c * x.powi(i as i32)).sum()
+            },
+            Model::PowerLaw => self.coefficients[0] * x.powf(self.coefficients[1])
+        };
+    }
+
+    /// The fitted equation, e.g. `0.12000 + 0.03400*x`
+    pub fn describe(&self) -> String {
+        return match self.model {
+            Model::Linear | Model::Polynomial => {
+                let terms: Vec<String> = self.coefficients.iter().enumerate().map(|(i, c)| match i {
+                    0 => format!("{:.5}", c),
+                    1 => format!("{:.5}*x", c),
+                    _ => format!("{:.5}*x^{}", c, i)
+                }).collect();
+                terms.join(" + ")
+            },
+            Model::PowerLaw => format!("{:.5}*x^{:.5}", self.coefficients[0], self.coefficients[1])
+        };
+    }
+}
+
+/// Least squares fit of a straight line, None for less than two distinct x values
+pub fn linear(x: &[f64], y: &[f64]) -> Option<Fit> {
+    let coefficients = least_squares(x, y, 1)?;
+    return Some(Fit{ model: Model::Linear, r_squared: r_squared(x, y, &coefficients, Model::Linear), coefficients });
+}
+
+/// Least squares fit of a polynomial of `degree`, None for less than `degree + 1` distinct x values
+pub fn polynomial(x: &[f64], y: &[f64], degree: usize) -> Option<Fit> {
+    let coefficients = least_squares(x, y, degree)?;
+    return Some(Fit{
+        model: Model::Polynomial,
+        r_squared: r_squared(x, y, &coefficients, Model::Polynomial),
+        coefficients
+    });
+}
+
+/// Fit of `y = a * x^b` by a straight line through `ln y` versus `ln x` - None unless every value is positive
+pub fn power_law(x: &[f64], y: &[f64]) -> Option<Fit> {
+    if x.iter().chain(y.iter()).any(|v| *v <= 0.) {
+        return None;
+    }
+
+    let ln_x: Vec<f64> = x.iter().map(|v| v.ln()).collect();
+    let ln_y: Vec<f64> = y.iter().map(|v| v.ln()).collect();
+    let line = least_squares(&ln_x, &ln_y, 1)?;
+    let coefficients = vec![line[0].exp(), line[1]];
+
+    return Some(Fit{ model: Model::PowerLaw, r_squared: r_squared(x, y, &coefficients, Model::PowerLaw), coefficients });
+}
+
+/// Coefficients of the polynomial of `degree` minimising the squared error, by solving the normal equations.
+///
+/// x is centered and scaled to [-1, 1] first, as the normal equations of raw input sizes are badly conditioned.
+#[allow(clippy::needless_range_loop)]
+fn least_squares(x: &[f64], y: &[f64], degree: usize) -> Option<Vec<f64>> {
+    let size = degree + 1;
+    if x.len() != y.len() || x.len() < size {
+        return None;
+    }
+
+    let center = stats::mean(x);
+    let scale = x.iter().map(|v| (v - center).abs()).fold(0., f64::max);
+    if scale == 0. || !scale.is_finite() {
+        return None;
+    }
+
+    // augmented matrix of sum(u^(i+j)) | sum(y*u^i) of the scaled u = (x - center) / scale
+    let mut matrix = vec![vec![0.; size + 1]; size];
+    for (xi, yi) in x.iter().zip(y.iter()) {
+        let ui = (xi - center) / scale;
+        for i in 0..size {
+            for j in 0..size {
+                matrix[i][j] += ui.powi((i + j) as i32);
+            }
+            matrix[i][size] += yi * ui.powi(i as i32);
+        }
+    }
+
+    return Some(unscale(&solve(matrix)?, center, scale));
+}
+
+/// Coefficients in x of the polynomial with coefficients `scaled` in u = (x - center) / scale
+fn unscale(scaled: &[f64], center: f64, scale: f64) -> Vec<f64> {
+    let mut out = vec![0.; scaled.len()];
+
+    // (x - center)^k = sum over j of binomial(k, j) * x^j * (-center)^(k - j)
+    for (k, c) in scaled.iter().enumerate() {
+        let mut binomial = 1.;
+        for (j, coefficient) in out.iter_mut().enumerate().take(k + 1) {
+            *coefficient += c / scale.powi(k as i32) * binomial * (-center).powi((k - j) as i32);
+            binomial = binomial * (k - j) as f64 / (j + 1) as f64;
+        }
+    }
+
+    return out;
+}
+
+/// Gaussian elimination with partial pivoting, None if the system is singular
+#[allow(clippy::needless_range_loop)]
+fn solve(mut matrix: Vec<Vec<f64>>) -> Option<Vec<f64>> {
+    let size = matrix.len();
+    // pivots are compared to the magnitude of the system rather than an absolute epsilon
+    let tolerance = 1e-12 * matrix.iter().flat_map(|row| row[..size].iter()).fold(0., |max: f64, v| max.max(v.abs()));
+
+    for col in 0..size {
+        let pivot = (col..size).max_by(|a, b| matrix[*a][col].abs().total_cmp(&matrix[*b][col].abs()))?;
+        if matrix[pivot][col].abs() <= tolerance {
+            return None;
+        }
+        matrix.swap(col, pivot);
+
+        for row in col + 1..size {
+            let factor = matrix[row][col] / matrix[col][col];
+            for k in col..=size {
+                matrix[row][k] -= factor * matrix[col][k];
+            }
+        }
+    }
+
+    let mut out = vec![0.; size];
+    for row in (0..size).rev() {
+        let known: f64 = (row + 1..size).map(|k| matrix[row][k] * out[k]).sum();
+        out[row] = (matrix[row][size] - known) / matrix[row][row];
+    }
+
+    return Some(out);
+}
+
+/// 1 for a perfect fit - constant y has no variance to explain, so only a perfect fit of it scores above 0
+fn r_squared(x: &[f64], y: &[f64], coefficients: &[f64], model: Model) -> f64 {
+    let fit = Fit{ model, coefficients: coefficients.to_vec(), r_squared: 0. };
+    let mean = stats::mean(y);
+    let ss_tot: f64 = y.iter().map(|v| (v - mean).powi(2)).sum();
+    let ss_res: f64 = x.iter().zip(y.iter()).map(|(xi, yi)| (yi - fit.predict(*xi)).powi(2)).sum();
+
+    if ss_tot == 0. {
+        let ss_y: f64 = y.iter().map(|v| v.powi(2)).sum();
+        return if ss_res <= 1e-12 * ss_y { 1. } else { 0. };
+    }
+
+    return 1. - ss_res / ss_tot;
+}
@@ -0,0 +1,121 @@
+use crate::common;
+use crate::error::{self, Error, Result};
+use crate::models;
+use crate::sources::{self, EnergySource};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Location of the hwmon class, relative to the sysfs root
+const HWMON_PATH: &str = "class/hwmon";
+
+/// hwmon drivers counting the energy of the CPU - others have `energyN_input` files too, e.g. i915 for the GPU
+pub const ENERGY_HWMON_DRIVERS: &[&str] = &["amd_energy", "zenergy", "zenpower"];
+
+/// Reads the `energyN_input` counters exposed by the hwmon drivers of AMD Zen, see `ENERGY_HWMON_DRIVERS`.
+///
+/// Socket counters (`Esocket0`) become `package-0`, per-core counters (`Ecore004`) become `core-4`.
+/// Unlabelled counters are namespaced by their driver, e.g. `zenergy/energy1`.
+pub struct HwmonSource {
+    base_path: PathBuf
+}
+
+impl HwmonSource {
+    pub fn new(sysfs_root: &Path) -> HwmonSource {
+        return HwmonSource{
+            base_path: sysfs_root.join(HWMON_PATH)
+        };
+    }
+}
+
+impl EnergySource for HwmonSource {
+    fn zones(&self) -> Result<Vec<models::RAPLZone>> {
+        let devices = match fs::read_dir(&self.base_path) {
+            Ok(d) => d,
+            Err(_) => return Ok(vec![])
+        };
+        let mut device_paths: Vec<PathBuf> = devices.filter_map(|d| Some(d.ok()?.path())).collect();
+        device_paths.sort();
+
+        let mut sockets: Vec<models::RAPLZone> = vec![];
+        let mut cores: Vec<models::RAPLZone> = vec![];
+
+        for device in device_paths {
+            let driver = fs::read_to_string(device.join("name")).unwrap_or_default();
+            if !ENERGY_HWMON_DRIVERS.contains(&driver.trim()) {
+                continue;
+            }
+
+            let mut inputs = energy_inputs(&device);
+            inputs.sort_by_key(|(n, _)| *n);
+
+            for (n, input) in inputs {
+                let label_path = device.join(format!("energy{}_label", n));
+                let (name, id) = match fs::read_to_string(&label_path) {
+                    Ok(label) => {
+                        let name = zone_name(label.trim()).ok_or_else(|| Error::malformed(
+                            label_path.display(), format!("{:?} is neither a socket nor a core counter", label.trim())))?;
+                        (name.to_owned(), name)
+                    },
+                    // unlabelled counters only mean something on their own device
+                    Err(_) => (format!("energy{}", n), sources::zone_id(driver.trim(), &format!("energy{}", n)))
+                };
+                if sockets.iter().chain(cores.iter()).any(|z| z.id == id) {
+                    return Err(Error::malformed(input.display(), format!("zone {} is counted by another device as well", id)));
+                }
+                let zone = models::RAPLZone{
+                    path: input.display().to_string(),
+                    id,
+                    name,
+                    parent: None,
+                    enabled: true,
+                    constraints: vec![]
+                };
+
+                if zone.name.starts_with("package-") {
+                    sockets.push(zone);
+                } else {
+                    cores.push(zone);
+                }
+            }
+        }
+
+        sockets.append(&mut cores);
+        return Ok(sockets);
+    }
+
+    fn read_energy(&self, path: &str) -> Result<f64> {
+        let reading = fs::read(path).map_err(|e| Error::read(path, e).with_hint(error::ENERGY_ACCESS_HINT))?;
+        let uj = common::reading_as_float(&reading).map_err(|e| Error::malformed(path, e))?;
+
+        return Ok(uj / common::UJ_TO_J_FACTOR);
+    }
+
+    fn read_energy_range(&self, _path: &str) -> Result<f64> {
+        // the drivers accumulate the 32 bit hardware counters into 64 bits themselves
+        return Ok(u64::MAX as f64 / common::UJ_TO_J_FACTOR);
+    }
+}
+
+/// All `energyN_input` files of a hwmon device, as (N, path)
+fn energy_inputs(device: &Path) -> Vec<(u64, PathBuf)> {
+    let entries = match fs::read_dir(device) {
+        Ok(e) => e,
+        Err(_) => return vec![]
+    };
+
+    return entries.filter_map(|e| {
+        let file_name = e.ok()?.file_name().to_string_lossy().to_string();
+        let n = file_name.strip_prefix("energy")?.strip_suffix("_input")?.parse::<u64>().ok()?;
+        Some((n, device.join(file_name)))
+    }).collect();
+}
+
+/// Zone name of the counter labelled `label`, None if its socket or core number is malformed
+fn zone_name(label: &str) -> Option<String> {
+    return match (label.strip_prefix("Esocket"), label.strip_prefix("Ecore")) {
+        (Some(socket), _) => socket.parse::<u64>().ok().map(|s| format!("package-{}", s)),
+        (_, Some(core)) => core.parse::<u64>().ok().map(|c| format!("core-{}", c)),
+        _ => Some(label.to_lowercase())
+    };
+}
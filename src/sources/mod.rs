@@ -0,0 +1,82 @@
+mod powercap;
+mod msr;
+mod perf;
+mod hwmon;
+
+pub use powercap::{PowercapSource, POWERCAP_PATH};
+pub use msr::MsrSource;
+pub use perf::{PerfSource, PerfEvent, discover_events, parse_event_config, parse_cpumask};
+pub use hwmon::{HwmonSource, ENERGY_HWMON_DRIVERS};
+
+use crate::error::Result;
+use crate::models;
+
+use std::path::Path;
+use std::sync::Arc;
+
+/// Shared handle to the energy source used by a measurement run
+pub type Source = Arc<dyn EnergySource>;
+
+/// A provider of cumulative energy counters, e.g. the powercap sysfs interface.
+///
+/// Zones are identified by their `path`, which is opaque to everything but the source itself.
+pub trait EnergySource: Send + Sync {
+    /// Enumerate all zones this source can measure
+    fn zones(&self) -> Result<Vec<models::RAPLZone>>;
+
+    /// Current counter value of the zone at `path` (J)
+    fn read_energy(&self, path: &str) -> Result<f64>;
+
+    /// Value at which the counter of the zone at `path` wraps around (J)
+    fn read_energy_range(&self, path: &str) -> Result<f64>;
+}
+
+/// Names accepted by `create_source`
+pub const SOURCES: &[&str] = &["auto", "powercap", "hwmon", "msr", "perf"];
+
+pub fn create_source(name: &str, sysfs_root: &Path, msr_dir: &Path) -> Result<Source> {
+    return Ok(match name {
+        "msr" => Arc::new(MsrSource::new(msr_dir, sysfs_root)),
+        "perf" => Arc::new(PerfSource::new(sysfs_root)?),
+        "hwmon" => Arc::new(HwmonSource::new(sysfs_root)),
+        "auto" => return create_source(detect_source(sysfs_root, msr_dir), sysfs_root, msr_dir),
+        _ => Arc::new(PowercapSource::new(sysfs_root))
+    });
+}
+
+/// Pick the first source readable by this user, in order of preference:
+/// powercap (Intel, and AMD on recent kernels), hwmon (amd_energy), perf and finally msr.
+pub fn detect_source(sysfs_root: &Path, msr_dir: &Path) -> &'static str {
+    if readable(&PowercapSource::new(sysfs_root)) {
+        return "powercap";
+    }
+    if readable(&HwmonSource::new(sysfs_root)) {
+        return "hwmon";
+    }
+    if PerfSource::new(sysfs_root).is_ok_and(|s| readable(&s)) {
+        return "perf";
+    }
+    if readable(&MsrSource::new(msr_dir, sysfs_root)) {
+        return "msr";
+    }
+
+    return "powercap";
+}
+
+/// Whether `source` has zones and the counter of the first one can be read -
+/// e.g. powercap lists its zones even when `energy_uj` is only readable by root
+fn readable(source: &dyn EnergySource) -> bool {
+    return match source.zones() {
+        Ok(zones) => zones.first().is_some_and(|z| source.read_energy(&z.path).is_ok()),
+        Err(_) => false
+    };
+}
+
+/// Identifier of the zone `name` below the zone (or namespace) `parent_id`
+pub fn zone_id(parent_id: &str, name: &str) -> String {
+    if parent_id.is_empty() {
+        return name.to_string();
+    }
+
+    return format!("{}/{}", parent_id, name);
+}
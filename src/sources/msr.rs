@@ -0,0 +1,190 @@
+use crate::error::{Error, Result};
+use crate::models;
+use crate::sources;
+use crate::sources::EnergySource;
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const MSR_RAPL_POWER_UNIT: u64 = 0x606;
+const MSR_PKG_ENERGY_STATUS: u64 = 0x611;
+const MSR_DRAM_ENERGY_STATUS: u64 = 0x619;
+const MSR_PP0_ENERGY_STATUS: u64 = 0x639;
+const MSR_PP1_ENERGY_STATUS: u64 = 0x641;
+const MSR_PLATFORM_ENERGY_STATUS: u64 = 0x64d;
+
+// energy status units live in bits 12:8 of MSR_RAPL_POWER_UNIT
+const ENERGY_UNIT_SHIFT: u64 = 8;
+const ENERGY_UNIT_MASK: u64 = 0x1f;
+// the energy counters are 32 bits wide
+const ENERGY_COUNTER_MASK: u64 = 0xffff_ffff;
+const ENERGY_COUNTER_RANGE: f64 = 4294967296.;
+
+const MSR_ACCESS_HINT: &str = "run as root, reading MSRs requires CAP_SYS_RAWIO";
+
+/// Reads the RAPL energy status registers directly through the `msr` driver, i.e. `/dev/cpu/N/msr`.
+///
+/// One CPU per package is used; zone paths are `<msr file>:<register>`.
+pub struct MsrSource {
+    msr_dir: PathBuf,
+    sysfs_root: PathBuf,
+    // opened once and kept, so polling doesn't reopen the device every read
+    files: Mutex<HashMap<String, File>>,
+    // joules per counter increment, per msr file
+    units: Mutex<HashMap<String, f64>>
+}
+
+impl MsrSource {
+    pub fn new(msr_dir: &Path, sysfs_root: &Path) -> MsrSource {
+        return MsrSource{
+            msr_dir: msr_dir.to_path_buf(),
+            sysfs_root: sysfs_root.to_path_buf(),
+            files: Mutex::new(HashMap::new()),
+            units: Mutex::new(HashMap::new())
+        };
+    }
+
+    /// The first CPU of each package, as (package id, msr file)
+    fn package_cpus(&self) -> Result<Vec<(u64, String)>> {
+        let entries = fs::read_dir(&self.msr_dir).map_err(|e| Error::SourceUnavailable{
+            source: "msr".to_string(),
+            reason: format!("couldn't read {}: {} - load the msr module with `modprobe msr`", self.msr_dir.display(), e)
+        })?;
+        let mut cpus: Vec<u64> = entries
+            .filter_map(|e| e.ok()?.file_name().to_str()?.parse::<u64>().ok())
+            .collect();
+        cpus.sort_unstable();
+
+        let mut packages: Vec<(u64, String)> = vec![];
+        for cpu in cpus {
+            let package = self.package_of(cpu);
+            if packages.iter().any(|(p, _)| *p == package) {
+                continue;
+            }
+
+            let msr_file = self.msr_dir.join(cpu.to_string()).join("msr");
+            if msr_file.exists() {
+                packages.push((package, msr_file.display().to_string()));
+            }
+        }
+
+        return Ok(packages);
+    }
+
+    fn package_of(&self, cpu: u64) -> u64 {
+        let topology = self.sysfs_root
+            .join(format!("devices/system/cpu/cpu{}/topology/physical_package_id", cpu));
+
+        // without topology information every cpu is assumed to sit on the same package
+        return match fs::read_to_string(topology) {
+            Ok(id) => id.trim().parse::<u64>().unwrap_or(0),
+            Err(_) => 0
+        };
+    }
+
+    fn energy_unit(&self, msr_file: &str) -> Result<f64> {
+        let mut units = self.units.lock().unwrap();
+        if let Some(unit) = units.get(msr_file) {
+            return Ok(*unit);
+        }
+
+        let raw = self.read_msr(msr_file, MSR_RAPL_POWER_UNIT).map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => Error::read(msr_file, e).with_hint(MSR_ACCESS_HINT),
+            _ => Error::SourceUnavailable{
+                source: "msr".to_string(),
+                reason: format!("couldn't read MSR_RAPL_POWER_UNIT from {}: {}", msr_file, e)
+            }
+        })?;
+        let unit = 1. / (1u64 << ((raw >> ENERGY_UNIT_SHIFT) & ENERGY_UNIT_MASK)) as f64;
+        units.insert(msr_file.to_string(), unit);
+
+        return Ok(unit);
+    }
+
+    /// The msr driver maps register numbers to file offsets
+    fn read_msr(&self, msr_file: &str, register: u64) -> io::Result<u64> {
+        let mut files = self.files.lock().unwrap();
+        let file = match files.entry(msr_file.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(File::open(msr_file)?)
+        };
+        let mut buf = [0u8; 8];
+        file.read_exact_at(&mut buf, register)?;
+
+        return Ok(u64::from_le_bytes(buf));
+    }
+}
+
+impl EnergySource for MsrSource {
+    fn zones(&self) -> Result<Vec<models::RAPLZone>> {
+        let mut zones: Vec<models::RAPLZone> = vec![];
+
+        for (package, msr_file) in self.package_cpus()? {
+            // fails unless the msr file is readable and the CPU has RAPL
+            self.energy_unit(&msr_file)?;
+
+            let mut registers = vec![
+                (MSR_PKG_ENERGY_STATUS, format!("package-{}", package)),
+                (MSR_PP0_ENERGY_STATUS, "core".to_string()),
+                (MSR_PP1_ENERGY_STATUS, "uncore".to_string()),
+                (MSR_DRAM_ENERGY_STATUS, "dram".to_string())
+            ];
+            // psys covers the whole platform, so it is only reported once
+            if zones.is_empty() {
+                registers.push((MSR_PLATFORM_ENERGY_STATUS, "psys".to_string()));
+            }
+
+            let pkg_path = format!("{}:{:#x}", msr_file, MSR_PKG_ENERGY_STATUS);
+            for (register, name) in registers {
+                // unsupported registers fail to read
+                if self.read_msr(&msr_file, register).is_err() {
+                    continue;
+                }
+
+                let (parent, parent_id) = match register {
+                    MSR_PP0_ENERGY_STATUS | MSR_PP1_ENERGY_STATUS | MSR_DRAM_ENERGY_STATUS =>
+                        (Some(pkg_path.to_owned()), format!("package-{}", package)),
+                    _ => (None, "".to_string())
+                };
+                zones.push(models::RAPLZone{
+                    path: format!("{}:{:#x}", msr_file, register),
+                    id: sources::zone_id(&parent_id, &name),
+                    name,
+                    parent,
+                    enabled: true,
+                    constraints: vec![]
+                });
+            }
+        }
+
+        return Ok(zones);
+    }
+
+    fn read_energy(&self, path: &str) -> Result<f64> {
+        let (msr_file, register) = parse_zone_path(path)?;
+        let raw = self.read_msr(msr_file, register)
+            .map_err(|e| Error::read(format!("register {:#x} of {}", register, msr_file), e).with_hint(MSR_ACCESS_HINT))?;
+
+        return Ok((raw & ENERGY_COUNTER_MASK) as f64 * self.energy_unit(msr_file)?);
+    }
+
+    fn read_energy_range(&self, path: &str) -> Result<f64> {
+        let (msr_file, _) = parse_zone_path(path)?;
+
+        return Ok(ENERGY_COUNTER_RANGE * self.energy_unit(msr_file)?);
+    }
+}
+
+fn parse_zone_path(path: &str) -> Result<(&str, u64)> {
+    let malformed = || Error::malformed(path, "not an MSR zone, expected <msr file>:<register>");
+    let (msr_file, register) = path.rsplit_once(':').ok_or_else(malformed)?;
+    let register = u64::from_str_radix(register.trim_start_matches("0x"), 16).map_err(|_| malformed())?;
+
+    return Ok((msr_file, register));
+}
@@ -0,0 +1,232 @@
+use crate::error::{Error, Result};
+use crate::models;
+use crate::sources;
+use crate::sources::EnergySource;
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+
+/// Location of the RAPL perf PMU, relative to the sysfs root
+pub const POWER_PMU_PATH: &str = "bus/event_source/devices/power";
+// size of the first published perf_event_attr layout, which is all we need
+const PERF_ATTR_SIZE_VER0: u32 = 64;
+
+const PERF_ACCESS_HINT: &str = "run as root, or grant CAP_PERFMON to raplrs with `sudo raplrs setup-permissions --perf`";
+
+/// An energy event of the power PMU, e.g. `energy-pkg`
+#[derive(Debug, Clone)]
+pub struct PerfEvent {
+    pub name: String,
+    pub config: u64,
+    pub scale: f64
+}
+
+#[repr(C)]
+struct PerfEventAttr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64
+}
+
+/// Reads the energy events of the `power` PMU through `perf_event_open`.
+///
+/// Events are opened once per package, on the CPUs listed in the PMU's `cpumask`; zone paths are `perf:<event>:<cpu>`.
+pub struct PerfSource {
+    zones: Vec<models::RAPLZone>,
+    counters: HashMap<String, (File, PerfEvent)>
+}
+
+impl PerfSource {
+    pub fn new(sysfs_root: &Path) -> Result<PerfSource> {
+        let pmu_path = sysfs_root.join(POWER_PMU_PATH);
+        if !pmu_path.exists() {
+            return Err(Error::SourceUnavailable{
+                source: "perf".to_string(),
+                reason: format!("there is no power PMU at {}", pmu_path.display())
+            });
+        }
+
+        let type_path = pmu_path.join("type");
+        let pmu_type = read_trimmed(&type_path)?.parse::<u32>().map_err(|e| Error::malformed(type_path.display(), e))?;
+        let cpus = parse_cpumask(&read_trimmed(&pmu_path.join("cpumask"))?)
+            .ok_or_else(|| Error::malformed(pmu_path.join("cpumask").display(), "not a cpu list"))?;
+        let events = discover_events(&pmu_path.join("events"))?;
+
+        let mut zones = vec![];
+        let mut counters = HashMap::new();
+
+        for (package, cpu) in cpus.iter().enumerate() {
+            let pkg_path = format!("perf:energy-pkg:{}", cpu);
+            for event in &events {
+                let name = match zone_name(&event.name, package) {
+                    Some(n) => n,
+                    None => continue
+                };
+                // psys covers the whole platform, so it is only reported once
+                if name == "psys" && package > 0 {
+                    continue;
+                }
+
+                let path = format!("perf:{}:{}", event.name, cpu);
+                let counter = open_counter(pmu_type, event.config, *cpu).map_err(|e| {
+                    let event = format!("perf event {} on cpu {}", event.name, cpu);
+                    match e.kind() {
+                        io::ErrorKind::PermissionDenied => Error::read(event, e).with_hint(PERF_ACCESS_HINT),
+                        _ => Error::SourceUnavailable{ source: "perf".to_string(), reason: format!("couldn't open {}: {}", event, e) }
+                    }
+                })?;
+
+                counters.insert(path.to_owned(), (counter, event.to_owned()));
+                let (parent, parent_id) = match event.name.as_str() {
+                    "energy-cores" | "energy-gpu" | "energy-ram" => (Some(pkg_path.to_owned()), format!("package-{}", package)),
+                    _ => (None, "".to_string())
+                };
+                let id = sources::zone_id(&parent_id, &name);
+                zones.push(models::RAPLZone{ path, name, id, parent, enabled: true, constraints: vec![] });
+            }
+        }
+
+        return Ok(PerfSource{ zones, counters });
+    }
+
+    fn counter(&self, path: &str) -> Result<&(File, PerfEvent)> {
+        return self.counters.get(path).ok_or_else(|| Error::malformed(path, "not a zone of the perf source"));
+    }
+}
+
+impl EnergySource for PerfSource {
+    fn zones(&self) -> Result<Vec<models::RAPLZone>> {
+        return Ok(self.zones.to_vec());
+    }
+
+    fn read_energy(&self, path: &str) -> Result<f64> {
+        let (counter, event) = self.counter(path)?;
+        let mut counter: &File = counter;
+        let mut buf = [0u8; 8];
+        counter.read_exact(&mut buf).map_err(|e| Error::read(path, e))?;
+
+        return Ok(u64::from_ne_bytes(buf) as f64 * event.scale);
+    }
+
+    fn read_energy_range(&self, path: &str) -> Result<f64> {
+        // perf accumulates into a 64 bit counter, so wrap-arounds are handled by the kernel
+        let (_, event) = self.counter(path)?;
+
+        return Ok(u64::MAX as f64 * event.scale);
+    }
+}
+
+/// Find all energy events and their scale in a PMU `events` directory.
+/// Events lacking a `.scale` file or not counted in joules are skipped.
+pub fn discover_events(events_dir: &Path) -> Result<Vec<PerfEvent>> {
+    let entries = fs::read_dir(events_dir).map_err(|e| Error::read(events_dir.display(), e))?;
+    let mut events = vec![];
+
+    for entry in entries {
+        let name = entry.map_err(|e| Error::read(events_dir.display(), e))?.file_name().to_string_lossy().to_string();
+        if !name.starts_with("energy-") || name.contains('.') {
+            continue;
+        }
+
+        let config = match parse_event_config(&read_trimmed(&events_dir.join(&name))?) {
+            Some(c) => c,
+            None => continue
+        };
+        let scale = match fs::read_to_string(events_dir.join(format!("{}.scale", name))) {
+            Ok(s) => s.trim().parse::<f64>().unwrap_or(0.),
+            Err(_) => continue
+        };
+        let unit = fs::read_to_string(events_dir.join(format!("{}.unit", name)))
+            .map(|u| u.trim().to_string())
+            .unwrap_or_else(|_| "Joules".to_string());
+
+        if scale > 0. && unit == "Joules" {
+            events.push(PerfEvent{ name, config, scale });
+        }
+    }
+
+    events.sort_by_key(|e| e.config);
+    return Ok(events);
+}
+
+/// Parse the `event=0x02` term of an event description
+pub fn parse_event_config(description: &str) -> Option<u64> {
+    for term in description.split(',') {
+        if let Some(value) = term.trim().strip_prefix("event=") {
+            return u64::from_str_radix(value.trim_start_matches("0x"), 16).ok();
+        }
+    }
+
+    return None;
+}
+
+/// Parse a cpu list such as `0,28` or `0-1`, None if it is malformed
+pub fn parse_cpumask(mask: &str) -> Option<Vec<i32>> {
+    let mut cpus = vec![];
+
+    for part in mask.split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let from = from.parse::<i32>().ok()?;
+                let to = to.parse::<i32>().ok()?;
+                cpus.extend(from..=to);
+            },
+            None => cpus.push(part.parse::<i32>().ok()?)
+        }
+    }
+
+    return Some(cpus);
+}
+
+fn zone_name(event: &str, package: usize) -> Option<String> {
+    return match event {
+        "energy-pkg" => Some(format!("package-{}", package)),
+        "energy-cores" => Some("core".to_string()),
+        "energy-gpu" => Some("uncore".to_string()),
+        "energy-ram" => Some("dram".to_string()),
+        "energy-psys" => Some("psys".to_string()),
+        _ => None
+    };
+}
+
+fn open_counter(pmu_type: u32, config: u64, cpu: i32) -> io::Result<File> {
+    let attr = PerfEventAttr{
+        type_: pmu_type,
+        size: PERF_ATTR_SIZE_VER0,
+        config,
+        sample_period: 0,
+        sample_type: 0,
+        read_format: 0,
+        flags: 0,
+        wakeup_events: 0,
+        bp_type: 0,
+        config1: 0
+    };
+
+    // system-wide counter on `cpu`: pid -1, no group, no flags
+    let fd = unsafe {
+        libc::syscall(libc::SYS_perf_event_open, &attr as *const PerfEventAttr, -1, cpu, -1, 0)
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    return Ok(unsafe { File::from_raw_fd(fd as i32) });
+}
+
+fn read_trimmed(path: &Path) -> Result<String> {
+    let content = fs::read_to_string(path).map_err(|e| Error::read(path.display(), e))?;
+    return Ok(content.trim().to_string());
+}
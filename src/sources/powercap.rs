@@ -0,0 +1,140 @@
+use crate::common;
+use crate::error::{self, Error, Result};
+use crate::models;
+use crate::sources;
+use crate::sources::EnergySource;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Location of the powercap class, relative to the sysfs root
+pub const POWERCAP_PATH: &str = "devices/virtual/powercap";
+/// Control types measured by the powercap source, e.g. `intel-rapl` and `intel-rapl-mmio`
+const CONTROL_TYPE_PREFIX: &str = "intel-rapl";
+
+/// Reads `energy_uj` and `max_energy_range_uj` of the zones of every `intel-rapl*` control type
+pub struct PowercapSource {
+    base_path: PathBuf
+}
+
+impl PowercapSource {
+    pub fn new(sysfs_root: &Path) -> PowercapSource {
+        return PowercapSource{
+            base_path: sysfs_root.join(POWERCAP_PATH)
+        };
+    }
+}
+
+impl EnergySource for PowercapSource {
+    /// Zones are returned depth first, i.e. every zone is directly followed by its subzones
+    fn zones(&self) -> Result<Vec<models::RAPLZone>> {
+        let mut zones: Vec<models::RAPLZone> = vec![];
+
+        for control_type in sorted_subdirs(&self.base_path) {
+            let name = dir_name(&control_type);
+            // zones are only listed inside their control type, e.g. intel-rapl/intel-rapl:0
+            if !name.starts_with(CONTROL_TYPE_PREFIX) || name.contains(':') {
+                continue;
+            }
+
+            // the plain intel-rapl zones keep their names as ids, others are namespaced, e.g. mmio/package-0
+            let id_prefix = name.trim_start_matches(CONTROL_TYPE_PREFIX).trim_start_matches('-').to_string();
+            walk_zones(&control_type, &name, None, &id_prefix, &mut zones)?;
+        }
+
+        return Ok(zones);
+    }
+
+    fn read_energy(&self, path: &str) -> Result<f64> {
+        return read_uj(path, "energy_uj");
+    }
+
+    fn read_energy_range(&self, path: &str) -> Result<f64> {
+        return read_uj(path, "max_energy_range_uj");
+    }
+}
+
+fn read_uj(zone_path: &str, file: &str) -> Result<f64> {
+    let file_path = format!("{}/{}", zone_path, file);
+    let reading = fs::read(&file_path).map_err(|e| Error::read(&file_path, e).with_hint(error::ENERGY_ACCESS_HINT))?;
+    let uj = common::reading_as_float(&reading).map_err(|e| Error::malformed(&file_path, e))?;
+
+    return Ok(uj / common::UJ_TO_J_FACTOR);
+}
+
+/// Add every zone directly below `dir` - named `<prefix>:N` - followed by its subzones, recursively
+fn walk_zones(dir: &Path, prefix: &str, parent: Option<String>, parent_id: &str,
+              zones: &mut Vec<models::RAPLZone>) -> Result<()> {
+    for item in sorted_subdirs(dir) {
+        let item_name = dir_name(&item);
+        let is_zone = match item_name.strip_prefix(prefix) {
+            Some(rest) => rest.starts_with(':') && !rest[1..].contains(':'),
+            None => false
+        };
+        if !is_zone {
+            continue;
+        }
+
+        let zone = parse_rapl_dir(&item, parent.to_owned(), parent_id)?;
+        let path = zone.path.to_owned();
+        let id = zone.id.to_owned();
+        zones.push(zone);
+
+        walk_zones(&item, &item_name, Some(path), &id, zones)?;
+    }
+
+    return Ok(());
+}
+
+fn parse_rapl_dir(item: &Path, parent: Option<String>, parent_id: &str) -> Result<models::RAPLZone> {
+    let item_path = item.display().to_string();
+    let name_path = format!("{}/name", item_path);
+    let item_name_data = fs::read(&name_path).map_err(|e| Error::read(&name_path, e))?;
+    let item_name = String::from_utf8_lossy(&item_name_data);
+
+    // zones without an enabled file can't be toggled, i.e. they are always on
+    let enabled = match fs::read_to_string(item.join("enabled")) {
+        Ok(e) => e.trim() != "0",
+        Err(_) => true
+    };
+
+    let mut constraints = vec![];
+    for n in 0.. {
+        match fs::read_to_string(item.join(format!("constraint_{}_name", n))) {
+            Ok(c) => constraints.push(c.trim().to_string()),
+            Err(_) => break
+        }
+    }
+
+    let name = item_name.to_string().replace('\n', "");
+
+    return Ok(models::RAPLZone{
+        path: item_path,
+        id: sources::zone_id(parent_id, &name),
+        name,
+        parent,
+        enabled,
+        constraints
+    });
+}
+
+fn sorted_subdirs(dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return vec![]
+    };
+    let mut dirs: Vec<PathBuf> = entries.filter_map(|e| Some(e.ok()?.path())).filter(|p| p.is_dir()).collect();
+    // intel-rapl:10 sorts before intel-rapl:2 lexically, so compare the zone indices numerically
+    dirs.sort_by_key(|d| {
+        let name = dir_name(d);
+        let mut parts = name.split(':');
+        let control_type = parts.next().unwrap_or("").to_string();
+        (control_type, parts.map(|i| i.parse::<u64>().unwrap_or(0)).collect::<Vec<u64>>())
+    });
+
+    return dirs;
+}
+
+fn dir_name(dir: &Path) -> String {
+    return dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+}
@@ -0,0 +1,280 @@
+use serde::{Serialize, Deserialize};
+
+/// Confidence level of the intervals in `Summary`
+pub const CONFIDENCE_LEVEL: f64 = 0.95;
+
+/// Descriptive statistics of a series of observations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Summary {
+    pub n: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Bounds of the confidence interval of the mean, based on the t-distribution
+    pub ci_low: f64,
+    pub ci_high: f64
+}
+
+impl Summary {
+    pub fn of(values: &[f64]) -> Summary {
+        let mean = mean(values);
+        let std_dev = std_dev(values);
+        let margin = confidence_margin(values.len(), std_dev, CONFIDENCE_LEVEL);
+
+        return Summary{
+            n: values.len(),
+            mean,
+            median: median(values),
+            std_dev,
+            min: values.iter().cloned().fold(f64::NAN, f64::min),
+            max: values.iter().cloned().fold(f64::NAN, f64::max),
+            ci_low: mean - margin,
+            ci_high: mean + margin
+        };
+    }
+
+    /// Coefficient of variation, i.e. the standard deviation relative to the mean
+    pub fn cv(&self) -> f64 {
+        return self.std_dev / self.mean.abs();
+    }
+}
+
+pub fn mean(values: &[f64]) -> f64 {
+    return values.iter().sum::<f64>() / values.len() as f64;
+}
+
+pub fn median(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+
+    if sorted.len().is_multiple_of(2) {
+        return (sorted[mid - 1] + sorted[mid]) / 2.;
+    }
+    return sorted[mid];
+}
+
+/// Sample standard deviation
+pub fn std_dev(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.;
+    }
+
+    let m = mean(values);
+    let variance = values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+    return variance.sqrt();
+}
+
+/// Half-width of the confidence interval at `level` of the mean of `n` observations - NaN for less than two
+pub fn confidence_margin(n: usize, std_dev: f64, level: f64) -> f64 {
+    if n < 2 {
+        return f64::NAN;
+    }
+
+    return t_critical(level, (n - 1) as f64) * std_dev / (n as f64).sqrt();
+}
+
+/// Critical value of the two-sided confidence interval at `level` for `df` degrees of freedom
+pub fn t_critical(level: f64, df: f64) -> f64 {
+    return t_quantile(1. - (1. - level) / 2., df);
+}
+
+/// Quantile function of Student's t-distribution, found by bisection of its CDF
+pub fn t_quantile(p: f64, df: f64) -> f64 {
+    if p == 0.5 {
+        return 0.;
+    }
+
+    let (mut lo, mut hi) = (-1000., 1000.);
+    for _ in 0..200 {
+        let mid = (lo + hi) / 2.;
+        if t_cdf(mid, df) < p {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    return (lo + hi) / 2.;
+}
+
+/// CDF of Student's t-distribution
+pub fn t_cdf(t: f64, df: f64) -> f64 {
+    let x = df / (df + t * t);
+    let tail = 0.5 * incomplete_beta(df / 2., 0.5, x);
+
+    if t >= 0. {
+        return 1. - tail;
+    }
+    return tail;
+}
+
+/// Regularized incomplete beta function I_x(a, b), by its continued fraction (Numerical Recipes 6.4)
+pub fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0. {
+        return 0.;
+    }
+    if x >= 1. {
+        return 1.;
+    }
+
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1. - x).ln()).exp();
+    // the continued fraction converges quickly only below this point, use the symmetry relation above it
+    if x < (a + 1.) / (a + b + 2.) {
+        return front * beta_continued_fraction(a, b, x) / a;
+    }
+    return 1. - front * beta_continued_fraction(b, a, 1. - x) / b;
+}
+
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    const EPSILON: f64 = 1e-14;
+
+    let mut c = 1.;
+    let mut d = 1. - (a + b) * x / (a + 1.);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1. / d;
+    let mut h = d;
+
+    for m in 1..300 {
+        let m = m as f64;
+        for numerator in [
+            m * (b - m) * x / ((a + 2. * m - 1.) * (a + 2. * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2. * m) * (a + 2. * m + 1.))
+        ] {
+            d = 1. + numerator * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1. + numerator / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1. / d;
+            h *= d * c;
+        }
+
+        if (d * c - 1.).abs() < EPSILON {
+            break;
+        }
+    }
+
+    return h;
+}
+
+/// Natural logarithm of the gamma function (Lanczos approximation)
+pub fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146, -86.50532032941677, 24.01409824083091,
+        -1.231739572450155, 0.1208650973866179e-2, -0.5395239384953e-5
+    ];
+
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut series = 1.000000000190015;
+    for (i, c) in COEFFICIENTS.iter().enumerate() {
+        series += c / (x + 1. + i as f64);
+    }
+
+    return -tmp + (2.5066282746310005 * series / x).ln();
+}
+
+/// Welch's unequal variances t-test, returns (t, degrees of freedom, two-sided p-value)
+pub fn welch_t_test(a: &[f64], b: &[f64]) -> (f64, f64, f64) {
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let (va, vb) = (std_dev(a).powi(2) / na, std_dev(b).powi(2) / nb);
+    if va + vb == 0. {
+        return (f64::NAN, f64::NAN, f64::NAN);
+    }
+
+    let t = (mean(a) - mean(b)) / (va + vb).sqrt();
+    let df = (va + vb).powi(2) / (va.powi(2) / (na - 1.) + vb.powi(2) / (nb - 1.));
+    let p = 2. * (1. - t_cdf(t.abs(), df));
+
+    return (t, df, p);
+}
+
+/// Mann-Whitney U test with the normal approximation and tie correction, returns (U of `a`, two-sided p-value)
+pub fn mann_whitney_u(a: &[f64], b: &[f64]) -> (f64, f64) {
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let mut all: Vec<(f64, bool)> = a.iter().map(|v| (*v, true)).chain(b.iter().map(|v| (*v, false))).collect();
+    all.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    // average ranks of ties, and the tie correction term sum(t^3 - t)
+    let mut rank_sum_a = 0.;
+    let mut ties = 0.;
+    let mut i = 0;
+    while i < all.len() {
+        let mut j = i;
+        while j + 1 < all.len() && all[j + 1].0 == all[i].0 {
+            j += 1;
+        }
+
+        let rank = (i + j) as f64 / 2. + 1.;
+        let t = (j - i + 1) as f64;
+        ties += t.powi(3) - t;
+        rank_sum_a += rank * all[i..=j].iter().filter(|x| x.1).count() as f64;
+        i = j + 1;
+    }
+
+    let u = rank_sum_a - na * (na + 1.) / 2.;
+    let n = na + nb;
+    let sigma = (na * nb / 12. * ((n + 1.) - ties / (n * (n - 1.)))).sqrt();
+    if sigma == 0. {
+        return (u, f64::NAN);
+    }
+
+    // continuity corrected
+    let z = ((u - na * nb / 2.).abs() - 0.5).max(0.) / sigma;
+    return (u, 2. * (1. - normal_cdf(z)));
+}
+
+/// Cohen's d with pooled standard deviation
+pub fn cohens_d(a: &[f64], b: &[f64]) -> f64 {
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let pooled = (((na - 1.) * std_dev(a).powi(2) + (nb - 1.) * std_dev(b).powi(2)) / (na + nb - 2.)).sqrt();
+
+    return (mean(a) - mean(b)) / pooled;
+}
+
+/// Cliff's delta - the probability of a value of `a` being larger than one of `b`, minus the reverse
+pub fn cliffs_delta(a: &[f64], b: &[f64]) -> f64 {
+    let mut dominance = 0.;
+    for x in a {
+        for y in b {
+            if x > y {
+                dominance += 1.;
+            } else if x < y {
+                dominance -= 1.;
+            }
+        }
+    }
+
+    return dominance / (a.len() * b.len()) as f64;
+}
+
+/// CDF of the standard normal distribution
+pub fn normal_cdf(z: f64) -> f64 {
+    return 0.5 * erfc(-z / 2f64.sqrt());
+}
+
+/// Complementary error function, Chebyshev fit with fractional error below 1.2e-7 (Numerical Recipes 6.2)
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1. / (1. + 0.5 * z);
+    let poly = -z * z - 1.26551223 + t * (1.00002368 + t * (0.37409196 + t * (0.09678418 + t * (-0.18628806
+        + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let res = t * poly.exp();
+
+    if x >= 0. {
+        return res;
+    }
+    return 2. - res;
+}
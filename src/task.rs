@@ -1,5 +1,8 @@
 use std::collections::HashMap;
 use crate::common;
+use crate::error::Result;
+use crate::logger;
+use crate::sources::Source;
 
 use std::thread;
 use std::time::{Instant, SystemTime};
@@ -8,12 +11,14 @@ use std::sync::mpsc::Receiver;
 use crate::models::IsolateData;
 use std::time::Duration;
 
-pub(crate) fn spawn_measurement_thread(start_time: Instant, system_start_time: SystemTime,
-                                       recv: Receiver<i8>, poll_delay: u64, tool_name: String,
-                                       benchmark_name: String,
-                                       isolate_map: Option<HashMap<String, IsolateData>>) -> JoinHandle<()> {
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_measurement_thread(source: Source, start_time: Instant, system_start_time: SystemTime,
+                                recv: Receiver<i8>, poll_delay: u64, tool_name: String,
+                                benchmark_name: String,
+                                isolate_map: Option<HashMap<String, IsolateData>>) -> JoinHandle<Result<()>> {
     let thr = thread::spawn(move || {
-        let mut tzones = common::setup_rapl_data().to_owned();
+        let mut tzones = common::setup_rapl_data(source.as_ref())?;
+        let mut sink = logger::CsvSink::create(system_start_time, tool_name, benchmark_name)?;
         let mut prev_time = start_time.to_owned();
         // reassign locally - unsafe otherwise
         let trecv = recv;
@@ -25,24 +30,25 @@ pub(crate) fn spawn_measurement_thread(start_time: Instant, system_start_time: S
         while run {
             now = Instant::now();
             tzones = common::update_measurements(
-                tzones.to_owned(), now, start_time, prev_time, system_start_time,
-                tool_name.to_owned(), benchmark_name.to_owned(), isolate_map.to_owned()
-            );
+                source.as_ref(), tzones.to_owned(), now, start_time, prev_time, &mut sink, isolate_map.to_owned()
+            )?;
             prev_time = now;
 
             match trecv.recv_timeout(duration) {
                 Ok(msg) => {
                     if msg == common::THREAD_KILL {
                         let now = Instant::now();
-                        let _ = common::update_measurements(
-                            tzones.to_owned(), now, start_time, prev_time, system_start_time,
-                            tool_name.to_owned(), benchmark_name.to_owned(), isolate_map.to_owned()
-                        );
+                        common::update_measurements(
+                            source.as_ref(), tzones.to_owned(), now, start_time, prev_time, &mut sink,
+                            isolate_map.to_owned()
+                        )?;
                         run = false;                    }
                 },
                 Err(_) => {}
             }
         }
+
+        return sink.flush();
     });
 
     return thr;
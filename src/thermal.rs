@@ -0,0 +1,258 @@
+use crate::models;
+use crate::sources;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Location of the hwmon class, relative to the sysfs root
+const HWMON_PATH: &str = "class/hwmon";
+/// Location of the thermal class, relative to the sysfs root
+const THERMAL_PATH: &str = "class/thermal";
+/// hwmon drivers reporting CPU temperatures - Intel, and AMD from family 10h on - in order of preference.
+/// zenpower replaces k10temp, but both may be loaded
+const CPU_HWMON_DRIVERS: [&str; 3] = ["coretemp", "zenpower", "k10temp"];
+/// Thermal zone types backed by the CPU, for systems without a CPU hwmon driver
+const CPU_THERMAL_ZONES: [&str; 3] = ["x86_pkg_temp", "cpu-thermal", "cpu_thermal"];
+
+static THERMAL: OnceLock<Thermal> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SensorKind {
+    /// Temperature of the package as a whole - `Package id N`, `Tdie`, `Tctl` or a thermal zone
+    Package,
+    /// Temperature of a core, or of a die of cores on AMD (`TccdN`)
+    Core
+}
+
+/// A temperature file found under hwmon or thermal, reporting millidegrees Celsius
+#[derive(Debug, Clone)]
+pub struct TempSensor {
+    pub path: PathBuf,
+    /// `tempN_label` of hwmon sensors, e.g. `Core 3` or `Tctl`, the zone type of thermal zones
+    pub label: String,
+    pub kind: SensorKind,
+    /// Physical package the sensor belongs to, matching the N of RAPL's `package-N` zones
+    pub package: u32
+}
+
+impl TempSensor {
+    /// Current temperature (°C), None if the sensor can't be read
+    pub fn read(&self) -> Option<f64> {
+        let millidegrees = fs::read_to_string(&self.path).ok()?.trim().parse::<f64>().ok()?;
+        return Some(millidegrees / 1000.);
+    }
+}
+
+/// Temperatures of a package at one poll (°C), None where there is no readable sensor
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PackageTemps {
+    pub package: Option<f64>,
+    pub core_max: Option<f64>,
+    pub core_avg: Option<f64>
+}
+
+impl PackageTemps {
+    /// Package temperature from the first of `package` (Tdie preferred over Tctl, which may be offset on AMD),
+    /// core temperatures from `cores`
+    fn of(package: Vec<(&TempSensor, f64)>, cores: Vec<f64>) -> PackageTemps {
+        let package = package.iter()
+            .min_by_key(|(s, _)| if s.label == "Tdie" { 0 } else { 1 })
+            .map(|(_, t)| *t);
+
+        return PackageTemps{
+            package,
+            core_max: cores.iter().cloned().reduce(f64::max),
+            core_avg: if cores.is_empty() { None } else { Some(cores.iter().sum::<f64>() / cores.len() as f64) }
+        };
+    }
+}
+
+/// One poll of every sensor, split by package
+#[derive(Debug, Clone, Default)]
+pub struct Reading {
+    pub packages: BTreeMap<u32, PackageTemps>,
+    /// The hottest package, and the cores of all packages - for zones not belonging to a package, e.g. `psys`
+    pub system: PackageTemps
+}
+
+impl Reading {
+    /// Temperatures of the package of `zone_id` - `package-1/core` belongs to package 1
+    pub fn zone(&self, zone_id: &str) -> PackageTemps {
+        return match package_of(zone_id).and_then(|p| self.packages.get(&p)) {
+            Some(temps) => *temps,
+            None => self.system
+        };
+    }
+
+    /// Set the temperature columns of `zone` to those of its package
+    pub fn apply(&self, zone: &mut models::RAPLData) {
+        let temps = self.zone(&zone.zone);
+        zone.package_temp = temps.package;
+        zone.core_temp_max = temps.core_max;
+        zone.core_temp_avg = temps.core_avg;
+    }
+}
+
+/// The CPU temperature sensors of the system, found once and read on every poll
+#[derive(Debug, Clone)]
+pub struct Thermal {
+    pub sensors: Vec<TempSensor>
+}
+
+impl Thermal {
+    /// Find the sensors of the CPU hwmon drivers under `sysfs_root` - or the CPU thermal zones if there are none
+    pub fn discover(sysfs_root: &Path) -> Thermal {
+        let mut sensors = hwmon_sensors(sysfs_root);
+        if sensors.is_empty() {
+            sensors = thermal_zone_sensors(&sysfs_root.join(THERMAL_PATH));
+        }
+
+        return Thermal{ sensors };
+    }
+
+    /// Read every sensor once
+    pub fn read(&self) -> Reading {
+        let readings: Vec<(&TempSensor, f64)> = self.sensors.iter().filter_map(|s| Some((s, s.read()?))).collect();
+        let of_kind = |kind: SensorKind, package: Option<u32>| -> Vec<(&TempSensor, f64)> {
+            readings.iter()
+                .filter(|(s, _)| s.kind == kind && package.is_none_or(|p| s.package == p))
+                .cloned()
+                .collect()
+        };
+        let temps = |sensors: Vec<(&TempSensor, f64)>| -> Vec<f64> { sensors.iter().map(|(_, t)| *t).collect() };
+
+        let mut packages = BTreeMap::new();
+        for sensor in &self.sensors {
+            let p = sensor.package;
+            packages.entry(p).or_insert_with(|| {
+                PackageTemps::of(of_kind(SensorKind::Package, Some(p)), temps(of_kind(SensorKind::Core, Some(p))))
+            });
+        }
+
+        let hottest = packages.values().filter_map(|t: &PackageTemps| t.package).reduce(f64::max);
+        let mut system = PackageTemps::of(vec![], temps(of_kind(SensorKind::Core, None)));
+        system.package = hottest;
+
+        return Reading{ packages, system };
+    }
+}
+
+/// Discover the sensors under `sysfs_root`, used for every later temperature reading of the process
+pub fn init(sysfs_root: &Path) -> &'static Thermal {
+    return THERMAL.get_or_init(|| Thermal::discover(sysfs_root));
+}
+
+/// Sensors found by `init` - discovered under `/sys` if it was never called
+pub fn thermal() -> &'static Thermal {
+    return THERMAL.get_or_init(|| Thermal::discover(Path::new("/sys")));
+}
+
+/// Package of a zone id such as `package-0/dram` or `mmio/package-0`, None for zones outside of packages
+pub fn package_of(zone_id: &str) -> Option<u32> {
+    return zone_id.split('/').find_map(|part| part.strip_prefix("package-")?.parse::<u32>().ok());
+}
+
+fn sensor_kind(label: &str) -> SensorKind {
+    if label.starts_with("Core") || label.starts_with("Tccd") {
+        return SensorKind::Core;
+    }
+
+    return SensorKind::Package;
+}
+
+/// `tempN_input` files of every device of the preferred CPU hwmon driver, ordered by device and N.
+///
+/// coretemp labels its package sensor `Package id N`; the AMD drivers have one device per package,
+/// whose package is that of the CPUs local to it - or the order of the devices, if sysfs doesn't tell them apart
+fn hwmon_sensors(sysfs_root: &Path) -> Vec<TempSensor> {
+    let devices: Vec<(PathBuf, String)> = sorted_entries(&sysfs_root.join(HWMON_PATH)).into_iter().map(|device| {
+        let name = fs::read_to_string(device.join("name")).unwrap_or_default().trim().to_string();
+        (device, name)
+    }).collect();
+    let driver = match CPU_HWMON_DRIVERS.iter().find(|driver| devices.iter().any(|(_, name)| name == *driver)) {
+        Some(driver) => driver,
+        None => return vec![]
+    };
+    let devices: Vec<PathBuf> = devices.into_iter().filter(|(_, name)| name == driver).map(|(device, _)| device).collect();
+    let inputs: Vec<Vec<(PathBuf, String)>> = devices.iter().map(|device| labelled_inputs(device)).collect();
+
+    let mapped: Option<Vec<u32>> = devices.iter().zip(inputs.iter()).map(|(device, labelled)| {
+        labelled.iter()
+            .find_map(|(_, l)| l.strip_prefix("Package id ")?.parse::<u32>().ok())
+            .or_else(|| device_package(sysfs_root, device))
+    }).collect();
+    // e.g. without NUMA information every device is local to all CPUs, and maps to the first package
+    let packages = match mapped {
+        Some(packages) if packages.iter().collect::<BTreeSet<&u32>>().len() == packages.len() => packages,
+        _ => (0..devices.len() as u32).collect()
+    };
+
+    let mut sensors = vec![];
+    for (labelled, package) in inputs.into_iter().zip(packages) {
+        for (path, label) in labelled {
+            sensors.push(TempSensor{ path, kind: sensor_kind(&label), label, package });
+        }
+    }
+
+    return sensors;
+}
+
+/// `tempN_input` files of a hwmon device ordered by N, with their labels
+fn labelled_inputs(device: &Path) -> Vec<(PathBuf, String)> {
+    let mut inputs: Vec<(u32, PathBuf)> = sorted_entries(device).into_iter().filter_map(|path| {
+        let file = path.file_name()?.to_str()?.to_string();
+        let n = file.strip_prefix("temp")?.strip_suffix("_input")?.parse::<u32>().ok()?;
+        Some((n, path))
+    }).collect();
+    inputs.sort_by_key(|(n, _)| *n);
+
+    return inputs.into_iter().map(|(n, path)| {
+        let label = fs::read_to_string(device.join(format!("temp{}_label", n)))
+            .map(|l| l.trim().to_string())
+            .unwrap_or_else(|_| format!("temp{}", n));
+        (path, label)
+    }).collect();
+}
+
+/// Physical package of the device behind a hwmon device - a CPU, or the first CPU local to a PCI device
+fn device_package(sysfs_root: &Path, hwmon_device: &Path) -> Option<u32> {
+    let device = hwmon_device.join("device");
+    let topology = match fs::read_to_string(device.join("local_cpulist")) {
+        Ok(cpus) => {
+            let cpu = *sources::parse_cpumask(cpus.trim())?.first()?;
+            sysfs_root.join(format!("devices/system/cpu/cpu{}/topology/physical_package_id", cpu))
+        },
+        Err(_) => device.join("topology/physical_package_id")
+    };
+
+    return fs::read_to_string(topology).ok()?.trim().parse::<u32>().ok();
+}
+
+/// `temp` files of the thermal zones whose type is a CPU, one per package in the order of the zones
+fn thermal_zone_sensors(base_path: &Path) -> Vec<TempSensor> {
+    let mut sensors = vec![];
+
+    for zone in sorted_entries(base_path) {
+        let is_zone = zone.file_name().and_then(|f| f.to_str()).is_some_and(|f| f.starts_with("thermal_zone"));
+        let zone_type = fs::read_to_string(zone.join("type")).unwrap_or_default().trim().to_string();
+        if is_zone && CPU_THERMAL_ZONES.contains(&zone_type.as_str()) {
+            let package = sensors.len() as u32;
+            sensors.push(TempSensor{ path: zone.join("temp"), label: zone_type, kind: SensorKind::Package, package });
+        }
+    }
+
+    return sensors;
+}
+
+/// Entries of `dir` in numeric order, so `hwmon10` comes after `hwmon9`
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(d) => d.filter_map(|e| Some(e.ok()?.path())).collect(),
+        Err(_) => return vec![]
+    };
+    entries.sort_by_key(|e| (e.as_os_str().len(), e.to_owned()));
+
+    return entries;
+}
@@ -1,35 +1,46 @@
 use crate::common;
+use crate::error::{Error, Result};
 use crate::task;
 use crate::models;
 use crate::logger;
+use crate::fixture;
+use crate::permissions;
+use crate::regression;
+use crate::sources::Source;
 
 use csv;
+use toml;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
 use std::collections::HashMap;
-use std::time::{Duration, Instant, SystemTime};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::thread;
 use std::sync::mpsc;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
 use std::io;
+use std::fs;
 use std::io::Write;
 
-pub(crate) fn live_measurement(poll_delay: u64, system_start_time: SystemTime, run_time_limit: Option<u64>, name: String) {
+pub fn live_measurement(source: &Source, poll_delay: u64, system_start_time: SystemTime, run_time_limit: Option<u64>,
+                        name: String) -> Result<()> {
     let tool_name = "live".to_string();
     let sleep = Duration::from_millis(poll_delay);
-    let mut zones = common::setup_rapl_data();
+    let mut zones = common::setup_rapl_data(source.as_ref())?;
     let run_time_limit = run_time_limit.unwrap_or(0);
 
     let start_time = Instant::now();
     let mut prev_time: Instant = start_time;
     #[allow(unused_assignments)]
     let mut now = start_time;
+    let mut sink = logger::CsvSink::create(system_start_time, tool_name, name)?;
 
     loop {
         now = Instant::now();
         zones = common::update_measurements(
-            zones.to_owned(), now, start_time, prev_time, system_start_time,
-            tool_name.to_owned(), name.to_owned(), None
-        );
+            source.as_ref(), zones.to_owned(), now, start_time, prev_time, &mut sink, None
+        )?;
 
         ncurses::clear();
         ncprint!("Press 'q' to quit\n");
@@ -37,8 +48,8 @@ pub(crate) fn live_measurement(poll_delay: u64, system_start_time: SystemTime, r
 
         prev_time = now;
 
-        if ncurses::getch() == common::KEY_CODE_EXIT {
-            ncurses::endwin();
+        if ncurses::getch() == common::KEY_CODE_EXIT || common::interrupted() {
+            common::kill_ncurses();
             break;
         }
 
@@ -52,65 +63,547 @@ pub(crate) fn live_measurement(poll_delay: u64, system_start_time: SystemTime, r
     print_headers!();
     print_result_line!(&zones);
     println!();
+
+    return Ok(());
 }
 
-pub(crate) fn do_benchmarks(poll_delay: u64, runner: Option<PathBuf>, program: PathBuf, args: Vec<String>,
-                            n: u64, name: String, isolate_file: Option<PathBuf>, interval: u64) {
-    let sleep = Duration::from_secs(interval);
-    for i in 0..n {
-        if n > 1 {
-            println!("Running benchmark iteration {}", i + 1);
+pub fn do_benchmarks(source: &Source, poll_delay: u64, program: &models::Program, name: String,
+                     isolate_file: Option<PathBuf>, options: &models::BenchmarkOptions) -> Result<models::BenchmarkSummary> {
+    let system_start_time = SystemTime::now();
+    let sleep = Duration::from_secs(options.interval);
+    let mut files = vec![];
+    let mut runs = vec![];
+
+    // warm-up runs are not measured, they only prime caches and the like
+    for i in 0..options.warmup {
+        println!("Running warm-up iteration {} (not measured)", i + 1);
+        run_program(program)?;
+
+        if options.interval > 0 {
+            println!("Sleeping for {} seconds before next benchmark run", options.interval);
+            thread::sleep(sleep);
         }
+    }
 
-        benchmark(poll_delay, runner.to_owned(), program.to_owned(),
-                  args.to_owned(), name.to_owned(), isolate_file.to_owned());
+    let start_time = Instant::now();
+    let mut cv = None;
+    let stop_reason = loop {
+        if options.n > 1 || options.target_cv.is_some() {
+            println!("Running benchmark iteration {}", runs.len() + 1);
+        }
+
+        let (file_name, zones) = benchmark(source, poll_delay, program, name.to_owned(), isolate_file.to_owned())?;
+        files.push(file_name);
+        runs.push(zones);
+
+        if options.target_cv.is_some() {
+            cv = Some(common::package_energy_cv(&runs));
+        }
+        match common::should_stop_benchmarking(options, runs.len() as u64, cv, start_time) {
+            Some(reason) => break reason,
+            None => {}
+        }
 
-        if interval > 0 && i + 1 < n {
-            println!("Sleeping for {} seconds before next benchmark run", interval);
+        if options.interval > 0 {
+            println!("Sleeping for {} seconds before next benchmark run", options.interval);
             thread::sleep(sleep);
         }
+    };
+
+    let summary = models::BenchmarkSummary{
+        name: name.to_owned(),
+        program: program.program.display().to_string(),
+        args: program.args.to_owned(),
+        warmup: options.warmup,
+        runs: runs.len(),
+        stop_reason,
+        cv,
+        files,
+        zones: common::summarize_runs(&runs)
+    };
+
+    if runs.len() > 1 {
+        println!("Summary of {} iterations", runs.len());
+        common::print_summary(&summary.zones);
     }
+    if options.target_cv.is_some() || options.max_time.is_some() {
+        println!("Stopped after {} runs: {}", runs.len(), common::describe_stop_reason(stop_reason, cv, options));
+    }
+    logger::log_benchmark_summary(&summary, system_start_time)?;
+
+    return Ok(summary);
 }
 
-pub(crate) fn benchmark(poll_delay: u64, runner: Option<PathBuf>, program: PathBuf, args: Vec<String>,
-                        name: String, isolate_file: Option<PathBuf>) {
+/// Run `program` once while measuring, returns the name of the .csv file and the last measurement
+pub fn benchmark(source: &Source, poll_delay: u64, program: &models::Program, name: String,
+                 isolate_file: Option<PathBuf>) -> Result<(String, Vec<models::RAPLData>)> {
     let tool_name = "benchmark".to_string();
-    let isolate_map = common::read_isolated_data(isolate_file);
+    let isolate_map = common::read_isolated_data(source.as_ref(), isolate_file)?;
+    // fail before running the program, e.g. without read access to the counters
+    common::setup_rapl_data(source.as_ref())?;
     let start_time = Instant::now();
     let iteration_start_time = SystemTime::now();
 
     let (send, recv) = mpsc::channel();
     let thr = task::spawn_measurement_thread(
-        start_time, iteration_start_time, recv, poll_delay,
+        source.clone(), start_time, iteration_start_time, recv, poll_delay,
         tool_name.to_owned(), name.to_owned(), isolate_map.to_owned());
 
-    match runner.to_owned() {
+    let res = run_program(program);
+
+    // the thread is gone already if measuring failed
+    let _ = send.send(common::THREAD_KILL);
+    thr.join().expect("Measurement thread panicked")?;
+    res?;
+
+    let file_name = common::create_log_file_name(name, tool_name, iteration_start_time)?;
+    let new_zones = common::get_last_measurement_from(PathBuf::from(file_name.to_owned()))?;
+
+    print_headers!();
+    print_result_line!(&new_zones);
+    println!();
+    common::check_interrupted()?;
+
+    return Ok((file_name, new_zones));
+}
+
+/// Benchmark `program` for every combination of the parameter axes, substituting `{name}` in its args
+pub fn sweep(source: &Source, poll_delay: u64, program: &models::Program, name: String, params: Vec<String>,
+             isolate_file: Option<PathBuf>, options: &models::BenchmarkOptions) -> Result<()> {
+    let system_start_time = SystemTime::now();
+    let axes = params.iter().map(|p| common::parse_sweep_axis(p)).collect::<Result<Vec<models::SweepAxis>>>()?;
+    let combinations = common::sweep_combinations(&axes);
+    let sleep = Duration::from_secs(options.interval);
+
+    for axis in &axes {
+        let placeholder = format!("{{{}}}", axis.name);
+        let used = program.program.to_string_lossy().contains(&placeholder)
+            || program.args.iter().any(|a| a.contains(&placeholder));
+        if !used {
+            return Err(Error::Usage(format!("Parameter {} is not used in the program or its args", placeholder)));
+        }
+    }
+
+    let mut points = vec![];
+    for (i, params) in combinations.iter().enumerate() {
+        let values: Vec<String> = params.iter().map(|(n, v)| format!("{}={}", n, v)).collect();
+        println!("Running sweep combination {} of {}: {}", i + 1, combinations.len(), values.join(" "));
+
+        let mut run = program.to_owned();
+        run.program = PathBuf::from(common::substitute_params(&program.program.to_string_lossy(), params));
+        run.args = program.args.iter().map(|a| common::substitute_params(a, params)).collect();
+        let run_name = if name.is_empty() { values.join("_") } else { format!("{}-{}", name, values.join("_")) };
+
+        let summary = do_benchmarks(source, poll_delay, &run, run_name, isolate_file.to_owned(), options)?;
+        points.push(models::SweepPoint{ params: params.to_owned(), summary });
+
+        if options.interval > 0 && i + 1 < combinations.len() {
+            println!("Sleeping for {} seconds before next benchmark run", options.interval);
+            thread::sleep(sleep);
+        }
+    }
+
+    println!("Sweep results");
+    common::print_sweep_results(&axes, &points);
+    logger::log_sweep_results(name, &axes, &points, system_start_time)?;
+
+    return Ok(());
+}
+
+/// Zone, values of the other parameters, and the parameter and energy of each point
+type SweepSeries = (String, Vec<(String, String)>, Vec<f64>, Vec<f64>);
+
+/// Fit linear, polynomial and power-law models of energy versus parameter `x` to the table written by `sweep`
+pub fn analyze(file: PathBuf, x: Option<String>, zone: Option<String>, degree: usize, predict: Vec<f64>,
+               name: String) -> Result<()> {
+    let system_start_time = SystemTime::now();
+    let mut rdr = csv::Reader::from_path(&file).map_err(|e| common::csv_error(&file, e))?;
+    let headers: Vec<String> = rdr.headers().map_err(|e| common::csv_error(&file, e))?.iter().map(|h| h.to_string()).collect();
+    let column = |name: &str| headers.iter().position(|h| h == name)
+        .ok_or_else(|| Error::malformed(file.display(), format!("not a sweep results file, it has no {} column", name)));
+    let zone_col = column("zone")?;
+    let power_col = column("power_j")?;
+    let params = headers[..zone_col].to_vec();
+
+    let x = match x {
+        Some(x) => x,
+        None if params.len() == 1 => params[0].to_owned(),
+        None => return Err(Error::Usage(format!("Several parameters in {}, pick one with -x: {}",
+                                                file.display(), params.join(", "))))
+    };
+    let x_col = params.iter().position(|p| *p == x).ok_or_else(|| {
+        Error::Usage(format!("No parameter {} in {}, found: {}", x, file.display(), params.join(", ")))
+    })?;
+
+    // one series per zone and combination of the other parameters
+    let mut series: Vec<SweepSeries> = vec![];
+    for res in rdr.records() {
+        let record = res.map_err(|e| common::csv_error(&file, e))?;
+        if zone.as_ref().map(|z| z != &record[zone_col]).unwrap_or(false) {
+            continue;
+        }
+
+        let others: Vec<(String, String)> = params.iter().enumerate().filter(|(i, _)| *i != x_col)
+            .map(|(i, p)| (p.to_owned(), record[i].to_string())).collect();
+        let x_value: f64 = record[x_col].parse()
+            .map_err(|_| Error::Usage(format!("Parameter {} is not numeric: {}", x, &record[x_col])))?;
+        let y_value: f64 = record[power_col].parse()
+            .map_err(|_| Error::malformed(file.display(), format!("power_j is not numeric: {}", &record[power_col])))?;
+
+        match series.iter_mut().find(|s| s.0 == record[zone_col] && s.1 == others) {
+            Some(s) => {
+                s.2.push(x_value);
+                s.3.push(y_value);
+            },
+            None => series.push((record[zone_col].to_string(), others, vec![x_value], vec![y_value]))
+        }
+    }
+
+    let mut analyses = vec![];
+    for (zone, others, xs, ys) in series {
+        let fits = vec![regression::linear(&xs, &ys), regression::polynomial(&xs, &ys, degree), regression::power_law(&xs, &ys)];
+        let analysis = models::ScalingAnalysis{
+            zone,
+            params: others,
+            x: x.to_owned(),
+            points: xs.len(),
+            fits: fits.into_iter().flatten().map(|fit| models::ScalingFit{
+                predictions: predict.iter().map(|p| (*p, fit.predict(*p))).collect(),
+                fit
+            }).collect()
+        };
+
+        common::print_scaling_analysis(&analysis, &predict);
+        println!();
+        analyses.push(analysis);
+    }
+
+    logger::log_scaling_analysis(name, &analyses, system_start_time)?;
+
+    return Ok(());
+}
+
+/// Run every benchmark of a TOML manifest, copying the manifest to the session directory
+pub fn suite(source: &Source, poll_delay: u64, manifest_file: PathBuf, name: String,
+             run_as: Option<models::RunAs>) -> Result<()> {
+    let system_start_time = SystemTime::now();
+    let manifest = read_suite_manifest(&manifest_file)?;
+    let manifest_file = fs::canonicalize(&manifest_file).map_err(|e| Error::read(manifest_file.display(), e))?;
+    // canonical paths of files always have a parent
+    let base_dir = manifest_file.parent().unwrap().to_path_buf();
+    let name = suite_name(&manifest_file, name)?;
+
+    match logger::session() {
+        Some(session) => {
+            let copy = session.path("suite.toml");
+            fs::copy(&manifest_file, &copy).map_err(|e| Error::write(copy, e))?;
+        },
+        None => {}
+    }
+
+    let mut summaries = vec![];
+    for (i, bench) in manifest.benchmarks.iter().enumerate() {
+        println!("Running suite benchmark {} of {}: {}", i + 1, manifest.benchmarks.len(), bench.name);
+        let program = suite_program(bench, &base_dir, &run_as);
+        let isolate_file = bench.isolate_file.as_ref().map(|f| base_dir.join(f));
+
+        if bench.interactive {
+            let start_time = SystemTime::now();
+            let zones = benchmark_interactive(source, &program, poll_delay, start_time, true, None,
+                                              bench.name.to_owned(), isolate_file)?;
+            summaries.push(models::BenchmarkSummary{
+                name: bench.name.to_owned(),
+                program: program.program.display().to_string(),
+                args: program.args.to_owned(),
+                warmup: 0,
+                runs: 1,
+                stop_reason: models::StopReason::Iterations,
+                cv: None,
+                files: vec![common::create_log_file_name(bench.name.to_owned(), "benchmark-int".to_string(), start_time)?],
+                zones: common::summarize_runs(&[zones])
+            });
+        } else {
+            let options = models::BenchmarkOptions{
+                n: bench.n.unwrap_or(1),
+                interval: bench.interval.unwrap_or(0),
+                warmup: bench.warmup.unwrap_or(0),
+                target_cv: bench.target_cv,
+                max_runs: bench.max_runs.unwrap_or(100),
+                max_time: bench.max_time
+            };
+            summaries.push(do_benchmarks(source, poll_delay, &program, bench.name.to_owned(), isolate_file, &options)?);
+        }
+    }
+
+    for summary in &summaries {
+        println!("Summary of {} ({} runs)", summary.name, summary.runs);
+        common::print_summary(&summary.zones);
+    }
+
+    logger::log_suite_summary(&models::SuiteSummary{
+        name,
+        manifest: manifest_file.display().to_string(),
+        benchmarks: summaries
+    }, system_start_time)?;
+
+    return Ok(());
+}
+
+fn read_suite_manifest(manifest_file: &PathBuf) -> Result<models::SuiteManifest> {
+    let data = fs::read_to_string(manifest_file).map_err(|e| Error::read(manifest_file.display(), e))?;
+    let manifest: models::SuiteManifest = toml::from_str(&data).map_err(|e| Error::malformed(manifest_file.display(), e))?;
+
+    // interactive programs run once, until they exit
+    for bench in manifest.benchmarks.iter().filter(|b| b.interactive) {
+        let options = bench.run_options();
+        if !options.is_empty() {
+            return Err(Error::Usage(format!("Suite benchmark {} is interactive, so its run options don't apply - remove {} from {}",
+                                            bench.name, options.join(", "), manifest_file.display())));
+        }
+    }
+
+    return Ok(manifest);
+}
+
+/// `name` if set, otherwise the name in the manifest or its file name
+pub fn suite_name(manifest_file: &PathBuf, name: String) -> Result<String> {
+    if !name.is_empty() {
+        return Ok(name);
+    }
+
+    return Ok(match read_suite_manifest(manifest_file)?.name {
+        Some(n) => n,
+        None => manifest_file.file_stem().unwrap_or_default().to_string_lossy().to_string()
+    });
+}
+
+/// Resolve the paths of a suite benchmark against the directory of the manifest
+fn suite_program(bench: &models::SuiteBenchmark, base_dir: &PathBuf, run_as: &Option<models::RunAs>) -> models::Program {
+    let working_dir = match &bench.working_dir {
+        Some(dir) => base_dir.join(dir),
+        None => base_dir.to_owned()
+    };
+
+    return models::Program{
+        runner: bench.runner.as_ref().map(|r| resolve_path(&working_dir, r)),
+        program: resolve_path(&working_dir, &bench.program),
+        args: bench.args.to_owned(),
+        env: bench.env.to_owned(),
+        working_dir: Some(working_dir.to_owned()),
+        timeout: bench.timeout,
+        run_as: run_as.to_owned()
+    };
+}
+
+/// `path` relative to `dir` if it exists there, so e.g. `bash` is still looked up in PATH
+fn resolve_path(dir: &Path, path: &Path) -> PathBuf {
+    let resolved = dir.join(path);
+    if resolved.exists() {
+        return resolved;
+    }
+
+    return path.to_owned();
+}
+
+/// Benchmark several programs against each other, the first being the baseline.
+/// The programs run in a new random order every round, so thermal drift affects them alike.
+#[allow(clippy::too_many_arguments)]
+pub fn compare(source: &Source, poll_delay: u64, commands: Vec<String>, name: String,
+               isolate_file: Option<PathBuf>, options: &models::BenchmarkOptions, seed: Option<u64>,
+               run_as: Option<models::RunAs>) -> Result<()> {
+    let system_start_time = SystemTime::now();
+    let sleep = Duration::from_secs(options.interval);
+    let seed = seed.unwrap_or_else(|| {
+        system_start_time.duration_since(UNIX_EPOCH).expect("Failed to check duration").as_nanos() as u64
+    });
+    let mut rng = StdRng::seed_from_u64(seed);
+    let programs = commands.iter().map(|c| parse_command(c, &run_as)).collect::<Result<Vec<models::Program>>>()?;
+    let mut files: Vec<Vec<String>> = vec![vec![]; programs.len()];
+    let mut runs: Vec<Vec<Vec<models::RAPLData>>> = vec![vec![]; programs.len()];
+
+    for i in 0..options.warmup {
+        println!("Running warm-up round {} (not measured)", i + 1);
+        for program in &programs {
+            run_program(program)?;
+        }
+    }
+
+    for round in 0..options.n {
+        let mut order: Vec<usize> = (0..programs.len()).collect();
+        order.shuffle(&mut rng);
+        println!("Running comparison round {} of {}", round + 1, options.n);
+
+        for (pos, i) in order.iter().enumerate() {
+            println!("Running {}", commands[*i]);
+            let (file_name, zones) = benchmark(source, poll_delay, &programs[*i], compare_run_name(&name, *i),
+                                               isolate_file.to_owned())?;
+            files[*i].push(file_name);
+            runs[*i].push(zones);
+
+            let last = round + 1 == options.n && pos + 1 == order.len();
+            if options.interval > 0 && !last {
+                println!("Sleeping for {} seconds before next benchmark run", options.interval);
+                thread::sleep(sleep);
+            }
+        }
+    }
+
+    let mut summaries = vec![];
+    for (i, program) in programs.iter().enumerate() {
+        summaries.push(models::BenchmarkSummary{
+            name: compare_run_name(&name, i),
+            program: program.program.display().to_string(),
+            args: program.args.to_owned(),
+            warmup: options.warmup,
+            runs: runs[i].len(),
+            stop_reason: models::StopReason::Iterations,
+            cv: None,
+            files: files[i].to_owned(),
+            zones: common::summarize_runs(&runs[i])
+        });
+
+        println!("Summary of {}", commands[i]);
+        common::print_summary(&summaries[i].zones);
+    }
+
+    let comparisons = common::compare_runs(&commands, &runs);
+    println!("Compared to {}", commands[0]);
+    common::print_comparisons(&comparisons);
+
+    logger::log_comparison_summary(&models::ComparisonSummary{
+        name,
+        seed,
+        programs: summaries,
+        comparisons
+    }, system_start_time)?;
+
+    return Ok(());
+}
+
+/// Split a command such as `bash fib.sh 30` into the program and its args
+fn parse_command(command: &str, run_as: &Option<models::RunAs>) -> Result<models::Program> {
+    let mut parts = shell_words::split(command)
+        .map_err(|e| Error::Usage(format!("Couldn't parse command {:?}: {}", command, e)))?
+        .into_iter();
+    let program = parts.next().ok_or_else(|| Error::Usage("Commands to compare must not be empty".to_string()))?;
+
+    return Ok(models::Program{
+        runner: None,
+        program: PathBuf::from(program),
+        args: parts.collect(),
+        env: HashMap::new(),
+        working_dir: None,
+        timeout: None,
+        run_as: run_as.to_owned()
+    });
+}
+
+fn compare_run_name(name: &str, program: usize) -> String {
+    if name.is_empty() {
+        return format!("program{}", program);
+    }
+
+    return format!("{}-program{}", name, program);
+}
+
+/// Run `program` to completion - its stderr is kept, so the reason of a failure shows
+pub fn run_program(program: &models::Program) -> Result<()> {
+    let mut child = program_command(program).stdout(Stdio::null()).stderr(Stdio::inherit())
+        .spawn().map_err(|e| Error::program(program_path(program), e))?;
+    return wait_for_program(&mut child, program);
+}
+
+/// The runner of `program` if it has one - what is actually executed
+fn program_path(program: &models::Program) -> &PathBuf {
+    return program.runner.as_ref().unwrap_or(&program.program);
+}
+
+/// Command starting `program` through its runner, with its environment and working directory
+fn program_command(program: &models::Program) -> Command {
+    let mut command = match &program.runner {
         Some(r) => {
-            let _out = Command::new(&r).arg(&program).args(&args).output().expect("Failed to execute command");
+            let mut command = Command::new(r);
+            command.arg(&program.program);
+            command
         },
-        None => {
-            let _out = Command::new(&program).args(&args).output().expect("Failed to execute command");
+        None => Command::new(&program.program)
+    };
+    match &program.run_as {
+        Some(user) => permissions::drop_privileges(&mut command, user),
+        None => {}
+    }
+    command.args(&program.args).envs(&program.env);
+
+    if let Some(dir) = &program.working_dir {
+        command.current_dir(dir);
+    }
+
+    return command;
+}
+
+/// Wait for `child` to exit, killing it once the timeout of `program` has passed or SIGINT/SIGTERM was caught.
+/// Reaching the timeout is an error, just like exiting unsuccessfully on its own - a truncated run is no sample
+fn wait_for_program(child: &mut Child, program: &models::Program) -> Result<()> {
+    let wait_error = |e| Error::program(program_path(program), e);
+    let pid = child.id() as libc::pid_t;
+
+    // the signal handler and the timeout watcher kill the child, while this thread blocks until it exits
+    common::set_child(Some(pid));
+    let (send, recv) = mpsc::channel::<()>();
+    let watcher = program.timeout.map(|timeout| thread::spawn(move || {
+        let timed_out = recv.recv_timeout(Duration::from_secs(timeout)) == Err(mpsc::RecvTimeoutError::Timeout);
+        // the pid is only reaped once this thread is joined
+        if timed_out {
+            unsafe { libc::kill(pid, libc::SIGKILL); }
         }
+        timed_out
+    }));
+
+    let exited = wait_exited(pid);
+    common::set_child(None);
+    drop(send);
+    let timed_out = match watcher {
+        Some(watcher) => watcher.join().unwrap_or(false),
+        None => false
+    };
+    // only reap it once nothing may kill the pid anymore
+    let status = exited.and_then(|_| child.wait()).map_err(wait_error)?;
+
+    if timed_out {
+        let reason = format!("it was killed after reaching the timeout of {} seconds", program.timeout.unwrap_or_default());
+        return Err(Error::program(program_path(program), io::Error::new(io::ErrorKind::TimedOut, reason)));
+    } else if !status.success() && !common::interrupted() {
+        return Err(Error::program(program_path(program), io::Error::other(format!("it exited with {}", status))));
     }
 
-    send.send(common::THREAD_KILL).expect("Failed to contact measurement thread");
-    thr.join().expect("Failed to wait for measurement thread to finish");
+    return Ok(());
+}
 
-    let file_name = common::create_log_file_name(name, tool_name, iteration_start_time);
-    let new_zones = common::get_last_measurement_from(PathBuf::from(file_name));
+/// Block until `pid` has exited, without reaping it
+fn wait_exited(pid: libc::pid_t) -> io::Result<()> {
+    loop {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let res = unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT) };
+        if res == 0 {
+            return Ok(());
+        }
 
-    print_headers!();
-    print_result_line!(&new_zones);
-    println!();
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
 }
 
-pub(crate) fn benchmark_interactive(runner: Option<PathBuf>, program: PathBuf, poll_delay: u64,
-                                    system_start_time: SystemTime, background_log: bool,
-                                    run_time_limit: Option<u64>, name: String, isolate_file: Option<PathBuf>) {
+#[allow(clippy::too_many_arguments)]
+pub fn benchmark_interactive(source: &Source, program: &models::Program, poll_delay: u64,
+                             system_start_time: SystemTime, background_log: bool,
+                             run_time_limit: Option<u64>, name: String,
+                             isolate_file: Option<PathBuf>) -> Result<Vec<models::RAPLData>> {
     let tool_name = "benchmark-int".to_string();
     let sleep = Duration::from_millis(poll_delay);
-    let mut zones = common::setup_rapl_data();
-    let isolate_map = common::read_isolated_data(isolate_file);
+    let mut zones = common::setup_rapl_data(source.as_ref())?;
+    let isolate_map = common::read_isolated_data(source.as_ref(), isolate_file)?;
     let run_time_limit = run_time_limit.unwrap_or(0);
 
     let start_time = Instant::now();
@@ -121,48 +614,36 @@ pub(crate) fn benchmark_interactive(runner: Option<PathBuf>, program: PathBuf, p
     if background_log {
         let (send, recv) = mpsc::channel();
         let thr = task::spawn_measurement_thread(
-            start_time, system_start_time, recv, poll_delay, tool_name.to_owned(),
+            source.clone(), start_time, system_start_time, recv, poll_delay, tool_name.to_owned(),
             name.to_owned(), isolate_map.to_owned());
 
-        match runner.to_owned() {
-            Some(r) => {
-                let _out = Command::new(&r).arg(&program).stdout(Stdio::inherit())
-                    .stdin(Stdio::inherit()).stderr(Stdio::inherit()).output().expect("Couldn't execute command");
-            },
-            None => {
-                let _out = Command::new(program.to_owned()).stdout(Stdio::inherit())
-                    .stdin(Stdio::inherit()).stderr(Stdio::inherit()).output().expect("Couldn't execute command");
-            }
-        }
+        let res = program_command(program).stdout(Stdio::inherit()).stdin(Stdio::inherit())
+            .stderr(Stdio::inherit()).spawn().map_err(|e| Error::program(program_path(program), e))
+            .and_then(|mut child| wait_for_program(&mut child, program));
 
-        send.send(common::THREAD_KILL).expect("Failed to communicate with measurement thread");
-        thr.join().expect("Failed to wait for measurement thread to finish");
+        // the thread is gone already if measuring failed
+        let _ = send.send(common::THREAD_KILL);
+        thr.join().expect("Measurement thread panicked")?;
+        res?;
 
         now = Instant::now();
+        let mut sink = logger::CsvSink::create(system_start_time, tool_name, name)?;
         zones = common::update_measurements(
-            zones.to_owned(), now, start_time, prev_time, system_start_time,
-            tool_name.to_owned(), name.to_owned(), isolate_map
-        );
+            source.as_ref(), zones.to_owned(), now, start_time, prev_time, &mut sink, isolate_map
+        )?;
     } else {
-        match runner.to_owned() {
-            Some(r) => {
-                let _out = Command::new(&r).arg(&program).spawn().expect("Couldn't execute command");
-            },
-            None => {
-                let _out = Command::new(program.to_owned()).spawn().expect("Couldn't execute command");
-            }
-        }
+        let mut child = program_command(program).spawn().map_err(|e| Error::program(program_path(program), e))?;
+        let mut sink = logger::CsvSink::create(system_start_time, tool_name, name)?;
 
         loop {
             now = Instant::now();
             zones = common::update_measurements(
-                zones.to_owned(), now, start_time, prev_time, system_start_time,
-                tool_name.to_owned(), name.to_owned(), isolate_map.to_owned()
-            );
+                source.as_ref(), zones.to_owned(), now, start_time, prev_time, &mut sink, isolate_map.to_owned()
+            )?;
 
             ncurses::clear();
-            ncprint!(format!("Running application {:?}\n", program).as_str());
-            ncprint!(format!("'q' or ctrl+c to exit. Ctrl+c will kill {:?} as well\n", program).as_str());
+            ncprint!(format!("Running application {:?}\n", program.program).as_str());
+            ncprint!(format!("'q' or ctrl+c to exit, killing {:?} as well\n", program.program).as_str());
             print_result_line!(&zones, true);
 
             prev_time = now;
@@ -172,45 +653,139 @@ pub(crate) fn benchmark_interactive(runner: Option<PathBuf>, program: PathBuf, p
                 break;
             }
 
-            if ncurses::getch() == common::KEY_CODE_EXIT {
-                ncurses::endwin();
+            if ncurses::getch() == common::KEY_CODE_EXIT || common::interrupted() {
+                common::kill_ncurses();
                 break;
             }
 
             thread::sleep(sleep);
         }
+
+        // it may have exited in the meantime
+        let _ = child.kill();
+        child.wait().map_err(|e| Error::program(program_path(program), e))?;
     }
     print_headers!();
     print_result_line!(&zones);
     println!();
+    common::check_interrupted()?;
+
+    return Ok(zones);
 }
 
-pub(crate) fn list(input: String) {
+pub fn list(source: &Source, input: String) -> Result<()> {
     let choices = vec!["zones"];
     match input.as_str() {
         "zones" => {
-            for zone in common::list_rapl() {
-                println!("{:?}", zone);
+            let zones = source.zones()?;
+            println!("{:<28}{:<28}{:<10}{:<28}path", "zone", "id", "state", "constraints");
+
+            for zone in &zones {
+                let name = format!("{}{}", "  ".repeat(zone_depth(zone, &zones)), zone.name);
+                let state = if zone.enabled { "enabled" } else { "disabled" };
+                let constraints = if zone.constraints.is_empty() { "-".to_string() } else { zone.constraints.join(",") };
+                println!("{:<28}{:<28}{:<10}{:<28}{}", name, zone.id, state, constraints, zone.path);
             }
         },
         _ => {
-            println!("Malformed input, valid choices: {:?}", choices);
+            return Err(Error::Usage(format!("Malformed input, valid choices: {:?}", choices)));
+        }
+    }
+
+    return Ok(());
+}
+
+/// Report what the current user can read of every energy source and the temperature sensors.
+/// Fails if no energy source is readable
+pub fn doctor(sysfs_root: PathBuf, msr_dir: PathBuf) -> Result<()> {
+    let checks = permissions::preflight(&sysfs_root, &msr_dir);
+    println!("{:<14}{:<10}detail", "check", "status");
+
+    for check in &checks {
+        let status = match check.status {
+            permissions::CheckStatus::Ok => "ok",
+            permissions::CheckStatus::Warning => "warning",
+            permissions::CheckStatus::Failed => "failed"
+        };
+        println!("{:<14}{:<10}{}", check.name, status, check.detail);
+        match &check.fix {
+            Some(fix) => println!("{:<24}fix: {}", "", fix),
+            None => {}
         }
     }
+
+    let readable: Vec<&str> = checks.iter()
+        .filter(|c| c.status == permissions::CheckStatus::Ok && c.name != "temperature")
+        .map(|c| c.name.as_str())
+        .collect();
+    if readable.is_empty() {
+        return Err(Error::SourceUnavailable{
+            source: "auto".to_string(),
+            reason: "no energy source is readable by this user - see the checks above".to_string()
+        });
+    }
+    println!("\nReadable energy sources: {}", readable.join(", "));
+
+    return Ok(());
 }
 
-pub(crate) fn pretty_print(file: PathBuf) {
-    let last = common::get_last_measurement_from(file);
+/// Let members of `group` read the energy counters without root - with `dry_run` the changes are only printed
+pub fn setup_permissions(sysfs_root: PathBuf, group: String, user: Option<String>, perf: bool, dry_run: bool) -> Result<()> {
+    if !dry_run && !permissions::is_root() {
+        return Err(Error::Usage("setup-permissions changes system files and must be run as root - \
+                                 use sudo, or --dry-run to only print the changes".to_string()));
+    }
+
+    // the user invoking sudo, rather than root
+    let user = user.or_else(|| std::env::var("SUDO_USER").ok()).filter(|u| u != "root");
+    // the capability is granted to this very binary, wherever it was started from
+    let perf_binary = if perf {
+        Some(std::env::current_exe().and_then(fs::canonicalize).map_err(|e| Error::read("the raplrs binary", e))?)
+    } else {
+        None
+    };
+    let actions = permissions::setup_actions(&sysfs_root, &group, user.to_owned(), perf_binary)?;
+    println!("Warning: {}. Only add users to {} who may see that.\n", permissions::PLATYPUS_WARNING, group);
+
+    for action in &actions {
+        println!("{}", action.describe());
+        if !dry_run {
+            action.apply()?;
+        }
+    }
+    if user.is_none() {
+        println!("No user to add to {} - pass one with --user", group);
+    }
+
+    return Ok(());
+}
+
+fn zone_depth(zone: &models::RAPLZone, zones: &[models::RAPLZone]) -> usize {
+    let mut depth = 0;
+    let mut parent = zone.parent.to_owned();
+
+    while let Some(path) = parent {
+        depth += 1;
+        parent = zones.iter().find(|z| z.path == path).and_then(|z| z.parent.to_owned());
+    }
+
+    return depth;
+}
+
+pub fn pretty_print(file: PathBuf) -> Result<()> {
+    let last = common::get_last_measurement_from(file)?;
 
     print_headers!();
     print_result_line!(&last);
     println!();
+
+    return Ok(());
 }
 
-pub(crate) fn measure_isolate_data(poll_delay: u64, minutes: u64, system_start_time: SystemTime) {
+pub fn measure_isolate_data(source: &Source, poll_delay: u64, minutes: u64, system_start_time: SystemTime) -> Result<()> {
     let time_limit_sec = minutes * 60;
     let sleep = Duration::from_millis(poll_delay);
-    let mut zones = common::setup_rapl_data();
+    let mut zones = common::setup_rapl_data(source.as_ref())?;
 
     let start_time = Instant::now();
     let mut prev_time = start_time;
@@ -218,19 +793,19 @@ pub(crate) fn measure_isolate_data(poll_delay: u64, minutes: u64, system_start_t
     let mut now = start_time;
 
     println!("Measuring isolation data");
+    let mut sink = logger::CsvSink::create(system_start_time, "isolate".to_string(), "idle".to_string())?;
 
     loop {
         now = Instant::now();
         zones = common::update_measurements(
-            zones.to_owned(), now, start_time, prev_time, system_start_time,
-            "isolate".to_string(), "idle".to_string(), None
-        );
+            source.as_ref(), zones.to_owned(), now, start_time, prev_time, &mut sink, None
+        )?;
         prev_time = now;
 
         print!("\r{} / {} seconds elapsed", now.duration_since(start_time).as_secs(), time_limit_sec);
         io::stdout().flush().unwrap();
 
-        if time_limit_sec > 0 && now.duration_since(start_time).as_secs() >= time_limit_sec {
+        if (time_limit_sec > 0 && now.duration_since(start_time).as_secs() >= time_limit_sec) || common::interrupted() {
             break;
         }
 
@@ -241,23 +816,42 @@ pub(crate) fn measure_isolate_data(poll_delay: u64, minutes: u64, system_start_t
     print_headers!();
     print_result_line!(&zones);
     println!();
+
+    return Ok(());
 }
 
-pub(crate) fn generate_isolate_data(csv_file: PathBuf) {
-    let mut rdr = csv::Reader::from_path(csv_file).unwrap();
-    let zones = common::list_rapl();
+pub fn migrate_isolate_data(source: &Source, file: PathBuf) -> Result<()> {
+    // read_isolated_data returns a map for every file
+    let map = common::read_isolated_data(source.as_ref(), Some(file))?.unwrap();
+    logger::log_isolate_data(map)?;
+
+    return Ok(());
+}
+
+/// Isolation data of the zones in `csv_file` - the zones of the machine it was measured on, not necessarily this one.
+/// Returns the name of the .json file written
+pub fn generate_isolate_data(csv_file: PathBuf) -> Result<String> {
+    let samples = common::read_samples(csv_file.to_owned())?;
+    if samples.len() < 3 {
+        return Err(Error::malformed(csv_file.display(), "isolation data needs at least three samples"));
+    }
+    let zones: Vec<String> = samples.first().map(|s| s.iter().map(|z| z.zone.to_owned()).collect()).unwrap_or_default();
     let mut map = HashMap::new();
     let mut out_map = HashMap::new();
 
-    for res in rdr.deserialize() {
-        let r: models::RAPLData = res.unwrap();
+    for r in samples.into_iter().flatten() {
         map.entry(r.zone.to_owned()).or_insert(vec![]).push(r);
     }
 
     for zone in zones {
-        let zone_data = map.entry(zone.name.to_owned()).or_insert(vec![]);
+        let zone_data = map.entry(zone.to_owned()).or_insert(vec![]);
+        // zones may be missing from later samples, e.g. in files concatenated from different machines
+        if zone_data.len() < 3 {
+            return Err(Error::malformed(csv_file.display(), format!("zone {} has less than three samples", zone)));
+        }
         zone_data.remove(0);
         let data_len = zone_data.len();
+        let total = zone_data[data_len - 1].power_j;
         let mut power_j_step = vec![];
         let mut watts_step = vec![];
         let mut watts_since_last_step = vec![];
@@ -276,39 +870,67 @@ pub(crate) fn generate_isolate_data(csv_file: PathBuf) {
             watts_since_last_step.push(zone_data[n].watts_since_last);
         }
 
-        out_map.insert(zone.name, models::IsolateData{
+        out_map.insert(zone, models::IsolateData{
             power_j: models::StatData{
-                min: power_j_step.iter().cloned().fold(0./0., f64::min),
-                max: power_j_step.iter().cloned().fold(0./0., f64::max),
+                min: power_j_step.iter().cloned().fold(f64::NAN, f64::min),
+                max: power_j_step.iter().cloned().fold(f64::NAN, f64::max),
                 avg: power_j_step.iter().sum::<f64>() / power_j_step.len() as f64,
-                total: zone_data.last().unwrap().power_j
+                total
             },
             watts: models::StatData{
-                min: watts_step.iter().cloned().fold(0./0., f64::min),
-                max: watts_step.iter().cloned().fold(0./0., f64::max),
+                min: watts_step.iter().cloned().fold(f64::NAN, f64::min),
+                max: watts_step.iter().cloned().fold(f64::NAN, f64::max),
                 avg: watts_step.iter().sum::<f64>() / watts_step.len() as f64,
                 total: 0.
             },
             watts_since_last: models::StatData{
-                min: watts_since_last_step.iter().cloned().fold(0./0., f64::min),
-                max: watts_since_last_step.iter().cloned().fold(0./0., f64::max),
+                min: watts_since_last_step.iter().cloned().fold(f64::NAN, f64::min),
+                max: watts_since_last_step.iter().cloned().fold(f64::NAN, f64::max),
                 avg: watts_since_last_step.iter().sum::<f64>() / watts_since_last_step.len() as f64,
                 total: 0.
             },
             watt_h: models::StatData{
-                min: watt_h_step.iter().cloned().fold(0./0., f64::min),
-                max: watt_h_step.iter().cloned().fold(0./0., f64::max),
+                min: watt_h_step.iter().cloned().fold(f64::NAN, f64::min),
+                max: watt_h_step.iter().cloned().fold(f64::NAN, f64::max),
                 avg: watt_h_step.iter().sum::<f64>() / watt_h_step.len() as f64,
-                total: common::watt_hours(zone_data.last().unwrap().power_j)
+                total: common::watt_hours(total)
             },
             kwatt_h: models::StatData{
-                min: kwatt_h_step.iter().cloned().fold(0./0., f64::min),
-                max: kwatt_h_step.iter().cloned().fold(0./0., f64::max),
+                min: kwatt_h_step.iter().cloned().fold(f64::NAN, f64::min),
+                max: kwatt_h_step.iter().cloned().fold(f64::NAN, f64::max),
                 avg: kwatt_h_step.iter().sum::<f64>() / kwatt_h_step.len() as f64,
-                total: common::kwatt_hours(zone_data.last().unwrap().power_j)
+                total: common::kwatt_hours(total)
             }
         });
     }
 
-    logger::log_isolate_data(out_map);
-}
\ No newline at end of file
+    return logger::log_isolate_data(out_map);
+}
+
+pub fn fixture(spec_file: PathBuf, sysfs_root: PathBuf, step_delay: u64) -> Result<()> {
+    let data = fs::read(&spec_file).map_err(|e| Error::read(spec_file.display(), e))?;
+    let packages: Vec<models::FixtureZone> = serde_json::from_slice(&data).map_err(|e| Error::malformed(spec_file.display(), e))?;
+    let sleep = Duration::from_millis(step_delay);
+
+    let mut fake = fixture::FakePowercap::create(&sysfs_root, &packages)?;
+    let steps = fake.steps();
+    println!("Created fake powercap tree in {:?}", sysfs_root);
+
+    let mut step = 1;
+    print!("\r{} / {} steps", step, steps);
+    io::stdout().flush().unwrap();
+
+    loop {
+        thread::sleep(sleep);
+        if !fake.step()? || common::interrupted() {
+            break;
+        }
+
+        step += 1;
+        print!("\r{} / {} steps", step, steps);
+        io::stdout().flush().unwrap();
+    }
+    println!();
+
+    return Ok(());
+}
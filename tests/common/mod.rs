@@ -0,0 +1,45 @@
+//! Fake powercap trees shared by the integration tests
+
+#![allow(dead_code)]
+
+use raplrs::fixture::FakePowercap;
+use raplrs::models::FixtureZone;
+use raplrs::sources::PowercapSource;
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+pub fn zone(name: &str, max_energy_range_uj: u64, energy_uj: Vec<u64>) -> FixtureZone {
+    return FixtureZone{ name: name.to_string(), control_type: None, max_energy_range_uj, energy_uj, subzones: vec![] };
+}
+
+/// A fake tree of `packages` in a new temporary directory, with a source reading it
+pub fn fake_tree(packages: Vec<FixtureZone>) -> (TempDir, FakePowercap, PowercapSource) {
+    let root = tempfile::tempdir().unwrap();
+    let fake = FakePowercap::create(root.path(), &packages).unwrap();
+    let source = PowercapSource::new(root.path());
+
+    return (root, fake, source);
+}
+
+/// A fake hwmon device `dir` of the driver `name` below `root`, with an `energyN_input` per (label, µJ) -
+/// inputs without a label get no `energyN_label` file
+pub fn hwmon_device(root: &Path, dir: &str, name: &str, inputs: &[(Option<&str>, u64)]) {
+    let path = root.join("class/hwmon").join(dir);
+    fs::create_dir_all(&path).unwrap();
+    fs::write(path.join("name"), format!("{}\n", name)).unwrap();
+    for (i, (label, uj)) in inputs.iter().enumerate() {
+        fs::write(path.join(format!("energy{}_input", i + 1)), format!("{}\n", uj)).unwrap();
+        match label {
+            Some(label) => fs::write(path.join(format!("energy{}_label", i + 1)), format!("{}\n", label)).unwrap(),
+            None => {}
+        }
+    }
+}
+
+/// `start` advanced by `secs` seconds, so measurements don't depend on how fast the test runs
+pub fn at(start: Instant, secs: u64) -> Instant {
+    return start + Duration::from_secs(secs);
+}
@@ -0,0 +1,151 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+mod common;
+
+use raplrs::common::{read_samples, setup_rapl_data, update_measurements};
+use raplrs::logger::{self, CsvSink};
+use raplrs::models::{IsolateData, SessionManifest};
+use raplrs::tools;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime};
+use tempfile::TempDir;
+
+const J: u64 = 1_000_000;
+
+static OUTPUT_DIR: OnceLock<TempDir> = OnceLock::new();
+
+fn manifest() -> SessionManifest {
+    return SessionManifest{
+        tool: "test".to_string(),
+        name: "".to_string(),
+        command: vec![],
+        source: Some("powercap".to_string()),
+        isolate_file: None,
+        run_as: None,
+        start_time: 0.,
+        end_time: None,
+        host: raplrs::common::host_info(),
+        files: vec![]
+    };
+}
+
+/// Log into a temporary output directory - there is one session per process, shared by the tests
+fn start_session() {
+    OUTPUT_DIR.get_or_init(|| {
+        let dir = tempfile::tempdir().unwrap();
+        logger::start_session(dir.path(), manifest(), None).unwrap();
+        dir
+    });
+}
+
+/// Log one row per zone for every step of `packages` to the csv file of `name`, one second apart
+fn log_steps(name: &str, packages: Vec<raplrs::models::FixtureZone>) -> PathBuf {
+    start_session();
+    let (_root, mut fake, source) = common::fake_tree(packages);
+    let mut zones = setup_rapl_data(&source).unwrap();
+    let start = Instant::now();
+    let mut prev = start;
+    let mut sink = CsvSink::create(SystemTime::now(), "test".to_string(), name.to_string()).unwrap();
+
+    for secs in 1..fake.steps() as u64 {
+        fake.step().unwrap();
+        let now = common::at(start, secs);
+        zones = update_measurements(&source, zones, now, start, prev, &mut sink, None).unwrap();
+        prev = now;
+    }
+    sink.flush().unwrap();
+
+    return PathBuf::from(&sink.file_name);
+}
+
+#[test]
+fn csv_rows_follow_the_polls() {
+    let mut package = common::zone("package-0", 1000 * J, vec![0, 10 * J, 25 * J]);
+    package.subzones = vec![common::zone("dram", 1000 * J, vec![0, J, 2 * J])];
+    let file = log_steps("csv", vec![package]);
+
+    let content = fs::read_to_string(&file).unwrap();
+    assert!(content.starts_with(&format!("# raplrs csv schema {}\n", raplrs::common::CSV_SCHEMA_VERSION)));
+
+    let samples = read_samples(file).unwrap();
+    assert_eq!(samples.len(), 2);
+    let rows: Vec<(u64, String, f64)> = samples.iter().flatten().map(|r| (r.sample, r.zone.to_owned(), r.power_j)).collect();
+    assert_eq!(rows, vec![
+        (1, "package-0".to_string(), 10.),
+        (1, "package-0/dram".to_string(), 1.),
+        (2, "package-0".to_string(), 25.),
+        (2, "package-0/dram".to_string(), 2.)
+    ]);
+    assert!(samples.iter().flatten().all(|r| r.run_id == samples[0][0].run_id));
+    assert!((samples[1][0].watts - 12.5).abs() < 1e-9);
+}
+
+#[test]
+fn isolation_data_is_the_mean_step() {
+    // 10 J every second, except for the first poll which generate_isolate_data skips
+    let readings = vec![0, 5 * J, 15 * J, 25 * J, 35 * J];
+    let file = log_steps("idle", vec![common::zone("package-0", 1000 * J, readings)]);
+
+    let json_file = tools::generate_isolate_data(file).unwrap();
+    let map: HashMap<String, IsolateData> = serde_json::from_slice(&fs::read(json_file).unwrap()).unwrap();
+
+    let data = &map["package-0"];
+    assert_eq!(map.len(), 1);
+    assert!((data.power_j.avg - 10.).abs() < 1e-9);
+    assert!((data.power_j.min - 10.).abs() < 1e-9);
+    assert!((data.power_j.total - 35.).abs() < 1e-9);
+    assert!((data.watts_since_last.avg - 10.).abs() < 1e-9);
+}
+
+#[test]
+fn isolation_data_needs_three_samples() {
+    let file = log_steps("short", vec![common::zone("package-0", 1000 * J, vec![0, J, 2 * J])]);
+
+    assert!(matches!(tools::generate_isolate_data(file), Err(raplrs::Error::Malformed{ .. })));
+}
+
+#[test]
+fn isolation_data_needs_every_zone_in_three_samples() {
+    start_session();
+    let dir = OUTPUT_DIR.get().unwrap().path();
+    let file = dir.join("missing-zone.csv");
+    let mut content = format!("# raplrs csv schema {}\n", raplrs::common::CSV_SCHEMA_VERSION);
+    content.push_str("run_id,sample,timestamp,zone,time_elapsed,power_j,watts,watts_since_last,start_power,prev_power,prev_power_reading,package_temp,core_temp_max,core_temp_avg\n");
+    content.push_str("run,1,1.0,package-0,1.0,10.0,10.0,10.0,0.0,0.0,10.0,,,\n");
+    content.push_str("run,1,1.0,package-0/dram,1.0,1.0,1.0,1.0,0.0,0.0,1.0,,,\n");
+    for sample in 2..=4 {
+        content.push_str(&format!("run,{0},{0}.0,package-0,{0}.0,{1}.0,10.0,10.0,0.0,0.0,{1}.0,,,\n", sample, sample * 10));
+    }
+    fs::write(&file, content).unwrap();
+
+    match tools::generate_isolate_data(file) {
+        Err(raplrs::Error::Malformed{ reason, .. }) => assert!(reason.contains("package-0/dram"), "{}", reason),
+        res => panic!("expected a malformed file, got {:?}", res)
+    }
+}
+
+#[test]
+fn one_session_per_process() {
+    start_session();
+    let dir = tempfile::tempdir().unwrap();
+
+    assert!(matches!(logger::start_session(dir.path(), manifest(), None), Err(raplrs::Error::Usage(_))));
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn isolation_map_without_the_zone_is_an_error() {
+    start_session();
+    let (_root, mut fake, source) = common::fake_tree(vec![common::zone("package-0", 1000 * J, vec![0, J])]);
+    let zones = setup_rapl_data(&source).unwrap();
+    let start = Instant::now();
+    let mut sink = CsvSink::create(SystemTime::now(), "test".to_string(), "missing-zone".to_string()).unwrap();
+
+    fake.step().unwrap();
+    let res = update_measurements(&source, zones, common::at(start, 1), start, start, &mut sink, Some(HashMap::new()));
+    assert!(matches!(res, Err(raplrs::Error::Malformed{ .. })), "{:?}", res.map(|z| z.len()));
+}
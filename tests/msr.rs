@@ -0,0 +1,81 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use raplrs::common::{calculate_power_metrics, setup_rapl_data};
+use raplrs::sources::{EnergySource, MsrSource};
+
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const MSR_RAPL_POWER_UNIT: u64 = 0x606;
+const MSR_PKG_ENERGY_STATUS: u64 = 0x611;
+// energy status units of 2^-14 J, in bits 12:8
+const POWER_UNIT: u64 = 14 << 8;
+const JOULE: u64 = 1 << 14;
+
+/// A fake `<msr_dir>/0/msr` which ends right after MSR_PKG_ENERGY_STATUS, so the other registers are unsupported
+fn fake_msr(msr_dir: &Path) -> File {
+    fs::create_dir_all(msr_dir.join("0")).unwrap();
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(msr_dir.join("0/msr")).unwrap();
+    file.write_all_at(&POWER_UNIT.to_le_bytes(), MSR_RAPL_POWER_UNIT).unwrap();
+    file.write_all_at(&0u64.to_le_bytes(), MSR_PKG_ENERGY_STATUS).unwrap();
+
+    return file;
+}
+
+/// Registers are overwritten in place, the way the driver reads them, so an open file sees the new value
+fn write_counter(file: &File, value: u64) {
+    file.write_all_at(&value.to_le_bytes(), MSR_PKG_ENERGY_STATUS).unwrap();
+}
+
+#[test]
+fn registers_are_scaled_by_the_energy_unit() {
+    let root = tempfile::tempdir().unwrap();
+    let msr_dir = root.path().join("cpu");
+    let file = fake_msr(&msr_dir);
+    let source = MsrSource::new(&msr_dir, root.path());
+
+    let zones = source.zones().unwrap();
+    let msr_file = msr_dir.join("0/msr").display().to_string();
+    assert_eq!(zones.len(), 1);
+    assert_eq!(zones[0].id, "package-0");
+    assert_eq!(zones[0].path, format!("{}:0x611", msr_file));
+
+    write_counter(&file, 3 * JOULE + JOULE / 2);
+    assert_eq!(source.read_energy(&zones[0].path).unwrap(), 3.5);
+    // only the low 32 bits are the counter
+    write_counter(&file, (0xdead << 32) | JOULE);
+    assert_eq!(source.read_energy(&zones[0].path).unwrap(), 1.);
+    assert_eq!(source.read_energy_range(&zones[0].path).unwrap(), 262144.);
+}
+
+#[test]
+fn power_survives_counter_wraparound() {
+    let root = tempfile::tempdir().unwrap();
+    let msr_dir = root.path().join("cpu");
+    let file = fake_msr(&msr_dir);
+    let source = MsrSource::new(&msr_dir, root.path());
+
+    // 2 J before the 32 bit counter overflows
+    write_counter(&file, 0xffff_ffff + 1 - 2 * JOULE);
+    let zone = setup_rapl_data(&source).unwrap().remove(0);
+    let start = Instant::now();
+
+    write_counter(&file, 3 * JOULE);
+    let zone = calculate_power_metrics(&source, zone, start + Duration::from_secs(1), start, start).unwrap();
+    assert_eq!(zone.power_j, 5.);
+
+    write_counter(&file, 9 * JOULE);
+    let zone = calculate_power_metrics(&source, zone, start + Duration::from_secs(2), start, start + Duration::from_secs(1)).unwrap();
+    assert_eq!(zone.power_j, 11.);
+    assert_eq!(zone.watts_since_last, 6.);
+}
+
+#[test]
+fn missing_msr_dir_is_unavailable() {
+    let root = tempfile::tempdir().unwrap();
+    let source = MsrSource::new(&root.path().join("cpu"), root.path());
+
+    assert!(matches!(source.zones(), Err(raplrs::Error::SourceUnavailable{ .. })));
+}
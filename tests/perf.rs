@@ -0,0 +1,65 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use raplrs::sources::{discover_events, parse_cpumask, parse_event_config};
+
+use std::fs;
+use std::path::Path;
+
+/// An event of a fake PMU `events` directory, with optional .scale and .unit files
+fn write_event(events_dir: &Path, name: &str, description: &str, scale: Option<&str>, unit: Option<&str>) {
+    fs::write(events_dir.join(name), format!("{}\n", description)).unwrap();
+    match scale {
+        Some(s) => fs::write(events_dir.join(format!("{}.scale", name)), format!("{}\n", s)).unwrap(),
+        None => {}
+    }
+    match unit {
+        Some(u) => fs::write(events_dir.join(format!("{}.unit", name)), format!("{}\n", u)).unwrap(),
+        None => {}
+    }
+}
+
+#[test]
+fn energy_events_are_discovered() {
+    let events_dir = tempfile::tempdir().unwrap();
+    let dir = events_dir.path();
+    write_event(dir, "energy-ram", "event=0x03", Some("2.3283064365386963e-10"), Some("Joules"));
+    write_event(dir, "energy-pkg", "event=0x02", Some("2.3283064365386963e-10"), Some("Joules"));
+    write_event(dir, "energy-cores", "event=0x01", Some("2.3283064365386963e-10"), None);
+    // not an energy event, no scale, not joules and no event term
+    write_event(dir, "cycles", "event=0x3c", Some("1"), None);
+    write_event(dir, "energy-gpu", "event=0x04", None, Some("Joules"));
+    write_event(dir, "energy-psys", "event=0x05", Some("1e-3"), Some("Watts"));
+    write_event(dir, "energy-odd", "umask=0x01", Some("1"), Some("Joules"));
+
+    let events = discover_events(dir).unwrap();
+    let found: Vec<(&str, u64)> = events.iter().map(|e| (e.name.as_str(), e.config)).collect();
+
+    assert_eq!(found, vec![("energy-cores", 1), ("energy-pkg", 2), ("energy-ram", 3)]);
+    assert!(events.iter().all(|e| e.scale == 2f64.powi(-32)));
+}
+
+#[test]
+fn missing_events_dir_fails() {
+    let root = tempfile::tempdir().unwrap();
+
+    assert!(matches!(discover_events(&root.path().join("events")), Err(raplrs::Error::NotFound{ .. })));
+}
+
+#[test]
+fn event_config_is_the_event_term() {
+    assert_eq!(parse_event_config("event=0x02"), Some(2));
+    assert_eq!(parse_event_config("umask=0x01, event=0x1f"), Some(0x1f));
+    assert_eq!(parse_event_config("event=ff"), Some(255));
+    assert_eq!(parse_event_config("umask=0x01"), None);
+    assert_eq!(parse_event_config("event=0xzz"), None);
+}
+
+#[test]
+fn cpumasks_are_lists_and_ranges() {
+    assert_eq!(parse_cpumask("0"), Some(vec![0]));
+    assert_eq!(parse_cpumask("0,28"), Some(vec![0, 28]));
+    assert_eq!(parse_cpumask("0-2,8"), Some(vec![0, 1, 2, 8]));
+    assert_eq!(parse_cpumask(""), Some(vec![]));
+    assert_eq!(parse_cpumask("0-x"), None);
+    assert_eq!(parse_cpumask("a"), None);
+}
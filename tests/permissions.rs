@@ -0,0 +1,140 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+mod common;
+
+use raplrs::permissions::{self, Action, CheckStatus};
+
+use std::path::{Path, PathBuf};
+
+const J: u64 = 1_000_000;
+
+/// One package with a core subzone
+fn packages() -> Vec<raplrs::models::FixtureZone> {
+    let mut package = common::zone("package-0", 1000 * J, vec![0, J]);
+    package.subzones = vec![common::zone("core", 1000 * J, vec![0, J])];
+    return vec![package];
+}
+
+#[test]
+fn preflight_checks_every_source() {
+    let (root, _fake, _source) = common::fake_tree(packages());
+    let checks = permissions::preflight(root.path(), &root.path().join("dev/cpu"));
+
+    let statuses: Vec<(&str, CheckStatus)> = checks.iter().map(|c| (c.name.as_str(), c.status)).collect();
+    assert_eq!(statuses, vec![
+        ("powercap", CheckStatus::Ok),
+        ("hwmon", CheckStatus::Warning),
+        ("perf", CheckStatus::Warning),
+        ("msr", CheckStatus::Warning),
+        ("temperature", CheckStatus::Warning)
+    ]);
+    assert_eq!(checks[0].detail, "2 zones readable");
+    assert_eq!(checks[3].fix.as_deref(), Some("sudo modprobe msr"));
+}
+
+#[test]
+fn preflight_fails_on_a_broken_tree() {
+    let (root, _fake, _source) = common::fake_tree(packages());
+    // a zone whose counter is gone
+    std::fs::remove_file(root.path().join("devices/virtual/powercap/intel-rapl/intel-rapl:0/energy_uj")).unwrap();
+
+    let checks = permissions::preflight(root.path(), &root.path().join("dev/cpu"));
+    assert_eq!(checks[0].status, CheckStatus::Failed);
+    assert!(checks[0].detail.starts_with("1 of 2 zones unreadable"), "{}", checks[0].detail);
+}
+
+#[test]
+fn setup_grants_every_counter_to_the_group() {
+    let (root, _fake, _source) = common::fake_tree(packages());
+    let zone = |path: &str| root.path().join("devices/virtual/powercap/intel-rapl").join(path).join("energy_uj");
+
+    let actions = permissions::setup_actions(root.path(), "raplrs-test", Some("alice".to_string()), None).unwrap();
+    assert_eq!(actions.len(), 5);
+    assert!(matches!(&actions[0], Action::CreateGroup{ group } if group == "raplrs-test"));
+    assert!(matches!(&actions[1], Action::AddToGroup{ user, group } if user == "alice" && group == "raplrs-test"));
+    match &actions[2] {
+        Action::WriteFile{ path, contents } => {
+            assert_eq!(path, Path::new(permissions::UDEV_RULE_PATH));
+            assert_eq!(contents, &permissions::udev_rule("raplrs-test"));
+        },
+        a => panic!("expected the udev rule, got {:?}", a)
+    }
+    let granted: Vec<&PathBuf> = actions[3..].iter().filter_map(|a| match a {
+        Action::GrantRead{ path, group } if group == "raplrs-test" => Some(path),
+        _ => None
+    }).collect();
+    assert_eq!(granted, vec![&zone("intel-rapl:0"), &zone("intel-rapl:0/intel-rapl:0:0")]);
+}
+
+#[test]
+fn setup_restricts_the_perf_binary_before_granting_the_capability() {
+    let (root, _fake, _source) = common::fake_tree(packages());
+    let binary = PathBuf::from("/opt/raplrs/bin/raplrs");
+
+    let actions = permissions::setup_actions(root.path(), "raplrs-test", None, Some(binary.to_owned())).unwrap();
+    let n = actions.len();
+    assert!(matches!(&actions[n - 2], Action::RestrictExecutable{ path, group } if *path == binary && group == "raplrs-test"));
+    match &actions[n - 1] {
+        Action::GrantCapability{ path, capability, .. } => {
+            assert_eq!(path, &binary);
+            assert_eq!(capability, "cap_perfmon");
+        },
+        a => panic!("expected the capability, got {:?}", a)
+    }
+    assert!(actions[n - 1].describe().contains("Warning"));
+    // perf_event_paranoid is left alone
+    assert!(!actions.iter().any(|a| matches!(a, Action::WriteFile{ path, .. } if path.starts_with("/proc"))));
+}
+
+#[test]
+fn setup_rejects_unsafe_group_names() {
+    let (root, _fake, _source) = common::fake_tree(packages());
+
+    for group in ["x;rm -rf /", "Raplrs", "1group", "-g", "", "a'b", &"g".repeat(33)] {
+        let res = permissions::setup_actions(root.path(), group, None, None);
+        assert!(matches!(res, Err(raplrs::Error::Usage(_))), "{:?} was accepted", group);
+    }
+    assert!(permissions::is_group_name("_rapl-rs_2"));
+}
+
+#[test]
+fn udev_rule_hands_the_counters_to_the_group() {
+    let rule = permissions::udev_rule("power");
+    let lines: Vec<&str> = rule.lines().collect();
+
+    assert_eq!(lines.len(), 6);
+    assert!(lines[0].starts_with("# written by `raplrs setup-permissions`"));
+    assert!(lines[1].starts_with("# warning: ") && lines[1].contains("CVE-2020-8694"));
+    assert_eq!(lines[2], "ACTION==\"add\", SUBSYSTEM==\"powercap\", \
+                          RUN+=\"/bin/sh -c 'chgrp power /sys%p/energy_uj && chmod g+r /sys%p/energy_uj'\"");
+    assert_eq!(lines[3], "ACTION==\"add\", SUBSYSTEM==\"hwmon\", ATTR{name}==\"amd_energy\", \
+                          RUN+=\"/bin/sh -c 'chgrp power /sys%p/energy*_input && chmod g+r /sys%p/energy*_input'\"");
+    assert!(lines[4].contains("ATTR{name}==\"zenergy\"") && lines[5].contains("ATTR{name}==\"zenpower\""));
+    assert!(rule.ends_with('\n'));
+}
+
+#[test]
+#[ignore = "needs root"]
+fn programs_run_as_another_user_get_a_clean_environment() {
+    // switching groups takes root, even to the current ones - run with `sudo cargo test -- --ignored`
+    let user = raplrs::models::RunAs{ name: "nobody-test".to_string(), uid: 0, gid: 0, groups: vec![0], home: PathBuf::from("/nonexistent") };
+    let set_vars = ["HOME", "USER", "LOGNAME", "PATH", "XDG_RUNTIME_DIR"];
+
+    let mut command = std::process::Command::new("env");
+    permissions::drop_privileges(&mut command, &user);
+    let output = String::from_utf8(command.output().unwrap().stdout).unwrap();
+    let vars: Vec<(&str, &str)> = output.lines().filter_map(|l| l.split_once('=')).collect();
+
+    assert!(vars.contains(&("HOME", "/nonexistent")));
+    assert!(vars.contains(&("USER", "nobody-test")));
+    assert!(vars.iter().all(|(var, _)| permissions::is_kept_var(var) || set_vars.contains(var)), "{:?}", vars);
+    // the environment of the test itself, e.g. the CARGO_ variables, is only passed on as far as it is kept
+    for (var, value) in std::env::vars() {
+        let passed = vars.contains(&(var.as_str(), value.as_str()));
+        if permissions::is_kept_var(&var) {
+            assert!(passed, "{} was dropped", var);
+        } else if !set_vars.contains(&var.as_str()) {
+            assert!(!vars.iter().any(|(v, _)| *v == var), "{} was passed on", var);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+mod common;
+
+use raplrs::common::{calculate_power_metrics, setup_rapl_data};
+
+use std::time::Instant;
+
+const J: u64 = 1_000_000;
+
+#[test]
+fn power_follows_the_counter() {
+    let (_root, mut fake, source) = common::fake_tree(vec![common::zone("package-0", 1000 * J, vec![100 * J, 130 * J])]);
+    let zones = setup_rapl_data(&source).unwrap();
+    let start = Instant::now();
+
+    fake.step().unwrap();
+    let zone = calculate_power_metrics(&source, zones[0].to_owned(), common::at(start, 2), start, start).unwrap();
+
+    assert_eq!(zone.zone, "package-0");
+    assert!((zone.power_j - 30.).abs() < 1e-9);
+    assert!((zone.watts - 15.).abs() < 1e-9);
+    assert!((zone.prev_power_reading - 130.).abs() < 1e-9);
+}
+
+#[test]
+fn power_survives_counter_overflow() {
+    // wraps around at 1000 J between the second and third reading
+    let readings = vec![900 * J, 950 * J, 50 * J, 100 * J];
+    let (_root, mut fake, source) = common::fake_tree(vec![common::zone("package-0", 1000 * J, readings)]);
+    let mut zone = setup_rapl_data(&source).unwrap().remove(0);
+    let start = Instant::now();
+    let mut prev = start;
+    let mut expected = vec![50., 150., 200.].into_iter();
+
+    for secs in 1..=3 {
+        fake.step().unwrap();
+        let now = common::at(start, secs);
+        zone = calculate_power_metrics(&source, zone, now, start, prev).unwrap();
+        prev = now;
+
+        let expected = expected.next().unwrap();
+        assert!((zone.power_j - expected).abs() < 1e-9, "{} J after {} s, expected {} J", zone.power_j, secs, expected);
+    }
+    assert!((zone.watts - 200. / 3.).abs() < 1e-9);
+    // 50 J between the second and third second
+    assert!((zone.watts_since_last - 50.).abs() < 1e-9);
+}
+
+#[test]
+fn subzones_are_measured_separately() {
+    let mut package = common::zone("package-0", 1000 * J, vec![0, 10 * J]);
+    package.subzones = vec![common::zone("core", 1000 * J, vec![0, 4 * J])];
+    let (_root, mut fake, source) = common::fake_tree(vec![package]);
+    let zones = setup_rapl_data(&source).unwrap();
+    let start = Instant::now();
+
+    fake.step().unwrap();
+    let measured: Vec<(String, f64)> = zones.into_iter()
+        .map(|z| calculate_power_metrics(&source, z, common::at(start, 1), start, start).unwrap())
+        .map(|z| (z.zone, z.power_j))
+        .collect();
+
+    assert_eq!(measured, vec![("package-0".to_string(), 10.), ("package-0/core".to_string(), 4.)]);
+}
@@ -0,0 +1,34 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use raplrs::models::Program;
+use raplrs::tools;
+use raplrs::Error;
+
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+fn sleep(secs: &str, timeout: Option<u64>) -> Program {
+    return Program{
+        runner: None, program: PathBuf::from("sleep"), args: vec![secs.to_string()], env: HashMap::new(),
+        working_dir: None, timeout, run_as: None
+    };
+}
+
+#[test]
+fn program_within_its_timeout_succeeds() {
+    assert!(tools::run_program(&sleep("0", Some(10))).is_ok());
+}
+
+#[test]
+fn program_reaching_its_timeout_fails() {
+    let start = Instant::now();
+    let res = tools::run_program(&sleep("10", Some(1)));
+
+    assert!(start.elapsed() < Duration::from_secs(5), "not killed in time: {:?}", start.elapsed());
+    match res {
+        Err(Error::Program{ error, .. }) => assert_eq!(error.kind(), io::ErrorKind::TimedOut),
+        r => panic!("expected a timeout error, got {:?}", r)
+    }
+}
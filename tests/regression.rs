@@ -0,0 +1,83 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use raplrs::regression::{self, Model};
+
+fn assert_coefficients(coefficients: &[f64], expected: &[f64], tolerance: f64) {
+    assert_eq!(coefficients.len(), expected.len());
+    for (c, e) in coefficients.iter().zip(expected.iter()) {
+        assert!((c - e).abs() <= tolerance * e.abs().max(1.), "{:?} is not within {} of {:?}", coefficients, tolerance, expected);
+    }
+}
+
+#[test]
+fn linear_data_fits_exactly() {
+    let x: Vec<f64> = (1..=10).map(|v| v as f64).collect();
+    let y: Vec<f64> = x.iter().map(|v| 3. + 2. * v).collect();
+
+    let fit = regression::linear(&x, &y).unwrap();
+    assert_eq!(fit.model, Model::Linear);
+    assert_coefficients(&fit.coefficients, &[3., 2.], 1e-10);
+    assert!((fit.r_squared - 1.).abs() < 1e-12);
+    assert!((fit.predict(20.) - 43.).abs() < 1e-9);
+}
+
+#[test]
+fn linear_fit_of_noisy_data() {
+    // least squares gives 1.5 + 0.8x, explaining 3.2 of the total sum of squares of 4
+    let x = [1., 2., 3., 4.];
+    let y = [2.5, 2.5, 4.5, 4.5];
+
+    let fit = regression::linear(&x, &y).unwrap();
+    assert_coefficients(&fit.coefficients, &[1.5, 0.8], 1e-12);
+    assert!((fit.r_squared - 0.8).abs() < 1e-12);
+}
+
+#[test]
+fn quadratic_data_fits_exactly() {
+    let x: Vec<f64> = (0..=10).map(|v| v as f64).collect();
+    let y: Vec<f64> = x.iter().map(|v| 1. - 2. * v + 0.5 * v * v).collect();
+
+    let fit = regression::polynomial(&x, &y, 2).unwrap();
+    assert_coefficients(&fit.coefficients, &[1., -2., 0.5], 1e-9);
+    assert!((fit.r_squared - 1.).abs() < 1e-12);
+}
+
+#[test]
+fn quadratic_fit_of_large_input_sizes() {
+    // the raw normal equations of x around 1e4 are too badly conditioned to solve
+    let x: Vec<f64> = (0..=10).map(|v| 10_000. + 100. * v as f64).collect();
+    let y: Vec<f64> = x.iter().map(|v| 5. + 0.25 * v + 1e-6 * v * v).collect();
+
+    let fit = regression::polynomial(&x, &y, 2).unwrap();
+    assert_coefficients(&fit.coefficients, &[5., 0.25, 1e-6], 1e-6);
+    assert!((fit.r_squared - 1.).abs() < 1e-9);
+    for (xi, yi) in x.iter().zip(y.iter()) {
+        assert!((fit.predict(*xi) - yi).abs() < 1e-6 * yi);
+    }
+}
+
+#[test]
+fn power_law_data_fits_exactly() {
+    let x = [1., 2., 4., 8., 16.];
+    let y: Vec<f64> = x.iter().map(|v: &f64| 2. * v.powf(1.5)).collect();
+
+    let fit = regression::power_law(&x, &y).unwrap();
+    assert_coefficients(&fit.coefficients, &[2., 1.5], 1e-10);
+    assert!((fit.r_squared - 1.).abs() < 1e-12);
+    assert!(regression::power_law(&[0., 1.], &[1., 2.]).is_none());
+}
+
+#[test]
+fn constant_y_is_a_perfect_fit() {
+    let fit = regression::linear(&[1., 2., 3.], &[4., 4., 4.]).unwrap();
+
+    assert_coefficients(&fit.coefficients, &[4., 0.], 1e-12);
+    assert_eq!(fit.r_squared, 1.);
+}
+
+#[test]
+fn too_few_distinct_x_values_fail() {
+    assert!(regression::linear(&[2., 2., 2.], &[1., 2., 3.]).is_none());
+    assert!(regression::polynomial(&[1., 2., 2., 1.], &[1., 2., 3., 4.], 2).is_none());
+    assert!(regression::polynomial(&[1., 2.], &[1., 2.], 2).is_none());
+}
@@ -0,0 +1,62 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+mod common;
+
+use raplrs::sources::{self, EnergySource};
+
+const J: u64 = 1_000_000;
+
+#[test]
+fn detect_prefers_powercap() {
+    let (root, _fake, _source) = common::fake_tree(vec![common::zone("package-0", 1000 * J, vec![0, J])]);
+    common::hwmon_device(root.path(), "hwmon0", "amd_energy", &[(Some("Esocket0"), J)]);
+
+    assert_eq!(sources::detect_source(root.path(), &root.path().join("dev/cpu")), "powercap");
+}
+
+#[test]
+fn detect_skips_a_listed_but_unreadable_source() {
+    let (root, _fake, _source) = common::fake_tree(vec![common::zone("package-0", 1000 * J, vec![0, J])]);
+    common::hwmon_device(root.path(), "hwmon0", "amd_energy", &[(Some("Esocket0"), J)]);
+    // the zone is still listed, but its counter can't be read - like energy_uj restricted to root
+    let energy_uj = root.path().join("devices/virtual/powercap/intel-rapl/intel-rapl:0/energy_uj");
+    std::fs::remove_file(&energy_uj).unwrap();
+    std::fs::create_dir(&energy_uj).unwrap();
+
+    assert_eq!(sources::detect_source(root.path(), &root.path().join("dev/cpu")), "hwmon");
+}
+
+#[test]
+fn hwmon_ignores_other_energy_counters() {
+    let root = tempfile::tempdir().unwrap();
+    common::hwmon_device(root.path(), "hwmon0", "i915", &[(Some("gpu"), J)]);
+    common::hwmon_device(root.path(), "hwmon1", "amd_energy", &[(Some("Esocket0"), J), (Some("Ecore001"), J)]);
+    common::hwmon_device(root.path(), "hwmon2", "zenergy", &[(Some("Esocket1"), J)]);
+
+    let zones = sources::HwmonSource::new(root.path()).zones().unwrap();
+    let ids: Vec<&str> = zones.iter().map(|z| z.id.as_str()).collect();
+    assert_eq!(ids, vec!["package-0", "package-1", "core-1"]);
+}
+
+#[test]
+fn hwmon_namespaces_unlabelled_counters() {
+    let root = tempfile::tempdir().unwrap();
+    common::hwmon_device(root.path(), "hwmon0", "amd_energy", &[(None, J)]);
+    common::hwmon_device(root.path(), "hwmon1", "zenergy", &[(None, J)]);
+
+    let zones = sources::HwmonSource::new(root.path()).zones().unwrap();
+    let ids: Vec<(&str, &str)> = zones.iter().map(|z| (z.id.as_str(), z.name.as_str())).collect();
+    assert_eq!(ids, vec![("amd_energy/energy1", "energy1"), ("zenergy/energy1", "energy1")]);
+}
+
+#[test]
+fn hwmon_rejects_malformed_and_colliding_labels() {
+    for labels in [vec!["Esocketx"], vec!["Ecore"], vec!["Esocket0", "Esocket00"]] {
+        let root = tempfile::tempdir().unwrap();
+        let inputs: Vec<(Option<&str>, u64)> = labels.iter().map(|l| (Some(*l), J)).collect();
+        common::hwmon_device(root.path(), "hwmon0", "amd_energy", &inputs);
+
+        let res = sources::HwmonSource::new(root.path()).zones();
+        assert!(matches!(res, Err(raplrs::Error::Malformed{ .. })), "{:?} gave {:?}", labels, res);
+    }
+}
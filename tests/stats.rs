@@ -0,0 +1,83 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use raplrs::stats;
+
+// reference values computed with arbitrary precision from the textbook definitions
+
+fn assert_close(value: f64, expected: f64, tolerance: f64) {
+    assert!((value - expected).abs() <= tolerance, "{} is not within {} of {}", value, tolerance, expected);
+}
+
+#[test]
+fn welch_t_test_matches_reference() {
+    let (t, df, p) = stats::welch_t_test(&[1., 2., 3., 4., 5.], &[2., 4., 6., 8., 10.]);
+    assert_close(t, -1.8973665961010276, 1e-12);
+    assert_close(df, 5.882352941176471, 1e-12);
+    assert_close(p, 0.10753119493062724, 1e-8);
+
+    let a = [19.8, 20.4, 19.6, 17.8, 18.5, 18.9, 18.3, 18.9, 19.5, 22.0];
+    let b = [28.2, 26.6, 20.1, 23.3, 25.2, 22.1, 17.7, 27.6, 20.6, 13.7, 23.2, 17.5, 20.6, 18.0, 23.9, 21.6, 24.3, 20.4, 24.0, 13.2];
+    let (t, df, p) = stats::welch_t_test(&a, &b);
+    assert_close(t, -2.219240915823625, 1e-12);
+    assert_close(df, 24.49622312420124, 1e-10);
+    assert_close(p, 0.0359722710297967, 1e-8);
+}
+
+#[test]
+fn welch_t_test_of_constant_samples_is_undefined() {
+    let (t, df, p) = stats::welch_t_test(&[1., 1., 1.], &[1., 1., 1.]);
+    assert!(t.is_nan() && df.is_nan() && p.is_nan());
+}
+
+#[test]
+fn mann_whitney_u_matches_reference() {
+    let (u, p) = stats::mann_whitney_u(&[1., 2., 3., 4., 5.], &[6., 7., 8., 9., 10.]);
+    assert_eq!(u, 0.);
+    assert_close(p, 0.012185780355344808, 1e-8);
+
+    // ties share their average rank and shrink the variance
+    let (u, p) = stats::mann_whitney_u(&[1., 2., 2., 3.], &[2., 3., 4., 5.]);
+    assert_eq!(u, 2.5);
+    assert_close(p, 0.1366582477381475, 1e-8);
+}
+
+#[test]
+fn cohens_d_matches_reference() {
+    let a = [2., 4., 4., 4., 5., 5., 7., 9.];
+    let b: Vec<f64> = a.iter().map(|v| v + 2.).collect();
+
+    // a difference of 2 over a pooled standard deviation of sqrt(32 / 7)
+    assert_close(stats::cohens_d(&a, &b), -0.9354143466934853, 1e-12);
+    assert_close(stats::cohens_d(&b, &a), 0.9354143466934853, 1e-12);
+}
+
+#[test]
+fn cliffs_delta_counts_dominance() {
+    assert_eq!(stats::cliffs_delta(&[1., 2., 3.], &[1., 2., 3.]), 0.);
+    assert_eq!(stats::cliffs_delta(&[10., 20., 30.], &[1., 2., 3.]), 1.);
+    assert_eq!(stats::cliffs_delta(&[1., 2., 3.], &[10., 20., 30.]), -1.);
+    assert_eq!(stats::cliffs_delta(&[1., 2., 3., 4.], &[3., 3.]), -0.25);
+}
+
+fn reading(zone: &str, power_j: f64) -> raplrs::models::RAPLData {
+    return raplrs::models::RAPLData{
+        path: String::new(), run_id: String::new(), sample: 0, timestamp: 0., zone: zone.to_string(),
+        time_elapsed: 1., power_j, watts: power_j, watts_since_last: power_j, start_power: 0., prev_power: 0.,
+        prev_power_reading: 0., package_temp: None, core_temp_max: None, core_temp_avg: None
+    };
+}
+
+#[test]
+fn comparison_against_a_zone_without_energy_has_no_percentage() {
+    let names = vec!["baseline".to_string(), "other".to_string()];
+    let runs = vec![
+        vec![vec![reading("package-0", 10.), reading("dram", 0.)], vec![reading("package-0", 12.), reading("dram", 0.)]],
+        vec![vec![reading("package-0", 16.), reading("dram", 1.)], vec![reading("package-0", 17.), reading("dram", 2.)]]
+    ];
+
+    let comparisons = raplrs::common::compare_runs(&names, &runs);
+    assert_eq!(comparisons.len(), 2);
+    assert_close(comparisons[0].diff_percent.unwrap(), 50., 1e-12);
+    assert_eq!(comparisons[1].diff_percent, None);
+    assert_close(comparisons[1].diff_power_j, 1.5, 1e-12);
+}
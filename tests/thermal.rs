@@ -0,0 +1,107 @@
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use raplrs::thermal::{self, SensorKind, Thermal};
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+/// `class/hwmon/<hwmon>` of `driver`, with the (label, millidegrees) sensors, linked to the PCI device `pci`
+fn hwmon_device(sysfs_root: &Path, hwmon: &str, driver: &str, pci: Option<(&str, &str)>, sensors: &[(&str, u32)]) {
+    let device = sysfs_root.join("class/hwmon").join(hwmon);
+    fs::create_dir_all(&device).unwrap();
+    fs::write(device.join("name"), format!("{}\n", driver)).unwrap();
+    for (i, (label, millidegrees)) in sensors.iter().enumerate() {
+        fs::write(device.join(format!("temp{}_label", i + 1)), format!("{}\n", label)).unwrap();
+        fs::write(device.join(format!("temp{}_input", i + 1)), format!("{}\n", millidegrees)).unwrap();
+    }
+
+    match pci {
+        Some((address, local_cpulist)) => {
+            let pci_device = sysfs_root.join("devices/pci0000:00").join(address);
+            fs::create_dir_all(&pci_device).unwrap();
+            fs::write(pci_device.join("local_cpulist"), format!("{}\n", local_cpulist)).unwrap();
+            symlink(&pci_device, device.join("device")).unwrap();
+        },
+        None => {}
+    }
+}
+
+/// Two packages of four CPUs each, 0-3 on package `first_package`
+fn cpu_topology(sysfs_root: &Path, first_package: u32) {
+    for cpu in 0..8 {
+        let topology = sysfs_root.join(format!("devices/system/cpu/cpu{}/topology", cpu));
+        fs::create_dir_all(&topology).unwrap();
+        let package = if cpu < 4 { first_package } else { 1 - first_package };
+        fs::write(topology.join("physical_package_id"), format!("{}\n", package)).unwrap();
+    }
+}
+
+fn packages(thermal: &Thermal) -> Vec<(String, u32)> {
+    return thermal.sensors.iter().map(|s| (s.label.to_owned(), s.package)).collect();
+}
+
+#[test]
+fn amd_devices_map_to_the_package_of_their_cpus() {
+    let root = tempfile::tempdir().unwrap();
+    // the device of the CPUs 0-3 comes first, but they sit on package 1
+    cpu_topology(root.path(), 1);
+    hwmon_device(root.path(), "hwmon0", "k10temp", Some(("0000:00:18.3", "0-3")), &[("Tctl", 50000), ("Tccd1", 40000)]);
+    hwmon_device(root.path(), "hwmon1", "k10temp", Some(("0000:00:19.3", "4-7")), &[("Tctl", 60000), ("Tccd1", 45000)]);
+
+    let thermal = Thermal::discover(root.path());
+    assert_eq!(packages(&thermal), vec![
+        ("Tctl".to_string(), 1), ("Tccd1".to_string(), 1), ("Tctl".to_string(), 0), ("Tccd1".to_string(), 0)
+    ]);
+    assert_eq!(thermal.sensors[1].kind, SensorKind::Core);
+
+    let reading = thermal.read();
+    assert_eq!(reading.zone("package-0").package, Some(60.));
+    assert_eq!(reading.zone("package-1/core").core_max, Some(40.));
+    assert_eq!(reading.zone("psys").package, Some(60.));
+}
+
+#[test]
+fn devices_local_to_every_cpu_are_numbered_in_order() {
+    let root = tempfile::tempdir().unwrap();
+    cpu_topology(root.path(), 1);
+    hwmon_device(root.path(), "hwmon0", "k10temp", Some(("0000:00:18.3", "0-7")), &[("Tctl", 50000)]);
+    hwmon_device(root.path(), "hwmon1", "k10temp", Some(("0000:00:19.3", "0-7")), &[("Tctl", 60000)]);
+
+    let thermal = Thermal::discover(root.path());
+    assert_eq!(packages(&thermal), vec![("Tctl".to_string(), 0), ("Tctl".to_string(), 1)]);
+}
+
+#[test]
+fn zenpower_is_preferred_over_k10temp() {
+    let root = tempfile::tempdir().unwrap();
+    hwmon_device(root.path(), "hwmon0", "k10temp", None, &[("Tctl", 50000)]);
+    hwmon_device(root.path(), "hwmon1", "acpitz", None, &[("temp1", 30000)]);
+    hwmon_device(root.path(), "hwmon2", "zenpower", None, &[("Tdie", 48000), ("Tctl", 58000)]);
+
+    let thermal = Thermal::discover(root.path());
+    assert_eq!(packages(&thermal), vec![("Tdie".to_string(), 0), ("Tctl".to_string(), 0)]);
+    // Tctl may be offset, so Tdie is the package temperature
+    assert_eq!(thermal.read().zone("package-0").package, Some(48.));
+}
+
+#[test]
+fn coretemp_labels_name_the_package() {
+    let root = tempfile::tempdir().unwrap();
+    hwmon_device(root.path(), "hwmon0", "coretemp", None, &[("Package id 1", 70000), ("Core 0", 65000), ("Core 1", 67000)]);
+    hwmon_device(root.path(), "hwmon1", "coretemp", None, &[("Package id 0", 55000), ("Core 0", 50000)]);
+
+    let reading = Thermal::discover(root.path()).read();
+    assert_eq!(reading.zone("package-1").package, Some(70.));
+    assert_eq!(reading.zone("package-1").core_avg, Some(66.));
+    assert_eq!(reading.zone("package-0/dram").core_max, Some(50.));
+}
+
+#[test]
+fn zone_ids_name_their_package() {
+    assert_eq!(thermal::package_of("package-0"), Some(0));
+    assert_eq!(thermal::package_of("package-1/dram"), Some(1));
+    assert_eq!(thermal::package_of("mmio/package-2"), Some(2));
+    assert_eq!(thermal::package_of("psys"), None);
+    assert_eq!(thermal::package_of("mmio/psys"), None);
+}